@@ -0,0 +1,27 @@
+#!/bin/sh
+# Writes a config file for all feeds in a Pyth Lazer symbol list to stdout, with one feed group
+# per minimum channel, spread over connections of at most 20 feeds or the second argument. The
+# WebSocket URLs, auth header, cluster and private key are left to
+# ORACLE_WS_URLS, ORACLE_AUTH_HEADER, SOLANA_CLUSTER and ORACLE_PRIVATE_KEY.
+
+SYMBOLS_FILE="${1:-/usr/local/bin/pyth_lazer_list.json}"
+MAX_FEEDS_PER_CONNECTION="${2:-20}"
+
+cat <<EOL
+[[providers]]
+provider = "pyth-lazer"
+max_feeds_per_connection = $MAX_FEEDS_PER_CONNECTION
+EOL
+
+for CHANNEL in $(jq -r '[.[].min_channel] | unique | .[]' "$SYMBOLS_FILE"); do
+    PRICE_FEEDS=$(jq -c --arg channel "$CHANNEL" '[.[] | select(.min_channel == $channel) | .name]' "$SYMBOLS_FILE")
+
+    cat <<EOL
+
+[[feed_groups]]
+name = "$CHANNEL"
+provider = "pyth-lazer"
+channel = "$CHANNEL"
+feeds = $PRICE_FEEDS
+EOL
+done
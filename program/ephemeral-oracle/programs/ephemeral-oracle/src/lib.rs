@@ -3,7 +3,6 @@ mod state;
 use crate::state::UpdateData;
 use anchor_lang::prelude::borsh::{BorshSchema, BorshSerialize};
 use anchor_lang::prelude::*;
-use anchor_lang::require_keys_eq;
 use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
 use anchor_lang::solana_program::program::invoke_signed;
 use anchor_lang::solana_program::{system_instruction, system_program};
@@ -18,8 +17,10 @@ use pyth_solana_receiver_sdk::price_update::{PriceFeedMessage, PriceUpdateV2, Ve
 
 declare_id!("PriCems5tHihc6UDXDjzjeawomAwBduWMGAi8ZUjppd");
 
+/// Keys allowed to write price feeds. The pusher can spread its transactions over all of them;
+/// rotating a key means deploying with both the old and the new one listed.
 #[cfg(not(feature = "test-mode"))]
-const ORACLE_IDENTITY: Pubkey = pubkey!("MPUxHCpNUy3K1CSVhebAmTbcTCKVxfk9YMDcUP2ZnEA");
+const ORACLE_IDENTITIES: &[Pubkey] = &[pubkey!("MPUxHCpNUy3K1CSVhebAmTbcTCKVxfk9YMDcUP2ZnEA")];
 const SEED_PREFIX: &[u8] = b"price_feed";
 const DELEGATE_WITH_ANY_VALIDATOR_DISCRIMINATOR: u8 = 19;
 
@@ -379,7 +380,10 @@ pub struct PriceUpdateV3 {
 
 fn ensure_oracle(payer: &Signer) -> Result<()> {
     #[cfg(not(feature = "test-mode"))]
-    require_keys_eq!(payer.key(), ORACLE_IDENTITY, OracleError::Unauthorized);
+    require!(
+        ORACLE_IDENTITIES.contains(&payer.key()),
+        OracleError::Unauthorized
+    );
     Ok(())
 }
 
@@ -0,0 +1,142 @@
+use crate::feed_groups::FeedGroup;
+use crate::payers::{parse_keypair, PayerPool};
+use solana_sdk::pubkey::Pubkey;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+/// Serves a line based control protocol on a local Unix socket:
+///
+/// - `subscribe [<group>] <feed>` adds a feed to a feed group, which only needs naming when more
+///   than one is running
+/// - `unsubscribe <feed>` removes a feed from every feed group
+/// - `list` prints the feeds currently subscribed to, as `<group>=<feeds>` when more than one
+///   feed group is running
+/// - `add-payer <base58 keypair>` starts signing with another payer
+/// - `remove-payer <pubkey>` stops signing with a payer
+/// - `payers` prints the payers currently in use
+pub async fn run_admin_socket(
+    path: String,
+    feed_groups: Vec<Arc<FeedGroup>>,
+    payers: Arc<PayerPool>,
+) -> std::io::Result<()> {
+    let feed_groups = Arc::new(feed_groups);
+    // a socket left behind by a previous run would make bind fail, anything else at the path
+    // is kept and reported by bind
+    if std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    // the socket accepts payer private keys, so only the owner may connect. Peers are checked
+    // too, for connections made before the permissions were set.
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    let owner = std::fs::metadata(&path)?.uid();
+    info!(path = %path, "Admin socket listening");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        match stream.peer_cred() {
+            Ok(peer) if peer.uid() == owner => {}
+            Ok(peer) => {
+                warn!(
+                    uid = peer.uid(),
+                    "Rejected admin connection from another user"
+                );
+                continue;
+            }
+            Err(e) => {
+                warn!(error = ?e, "Rejected admin connection without peer credentials");
+                continue;
+            }
+        }
+        let feed_groups = feed_groups.clone();
+        let payers = payers.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_admin_connection(stream, feed_groups, payers).await {
+                warn!(error = ?e, "Admin connection failed");
+            }
+        });
+    }
+}
+
+async fn handle_admin_connection(
+    stream: UnixStream,
+    feed_groups: Arc<Vec<Arc<FeedGroup>>>,
+    payers: Arc<PayerPool>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => continue,
+            ["subscribe", feed] => match feed_groups.as_slice() {
+                [group] => subscribe(group, feed).await,
+                _ => "error: several feed groups are running, use 'subscribe <group> <feed>'"
+                    .to_string(),
+            },
+            ["subscribe", name, feed] => match feed_groups.iter().find(|group| group.name == *name)
+            {
+                Some(group) => subscribe(group, feed).await,
+                None => format!("error: unknown feed group '{}'", name),
+            },
+            ["unsubscribe", feed] => {
+                let mut changed = false;
+                for group in feed_groups.iter() {
+                    changed |= group.unsubscribe(feed);
+                }
+                if changed {
+                    "ok".to_string()
+                } else {
+                    "unchanged".to_string()
+                }
+            }
+            ["list"] => match feed_groups.as_slice() {
+                [group] => group.feeds().join(","),
+                _ => feed_groups
+                    .iter()
+                    .map(|group| format!("{}={}", group.name, group.feeds().join(",")))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            },
+            ["add-payer", key] => match parse_keypair(key) {
+                Ok(payer) => {
+                    if payers.add(payer) {
+                        "ok".to_string()
+                    } else {
+                        "unchanged".to_string()
+                    }
+                }
+                Err(e) => format!("error: invalid keypair: {}", e),
+            },
+            ["remove-payer", pubkey] => match Pubkey::from_str(pubkey) {
+                Ok(pubkey) => match payers.remove(&pubkey) {
+                    Ok(()) => "ok".to_string(),
+                    Err(e) => format!("error: {}", e),
+                },
+                Err(e) => format!("error: invalid pubkey: {}", e),
+            },
+            ["payers"] => payers
+                .pubkeys()
+                .iter()
+                .map(|pubkey| pubkey.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => "error: expected 'subscribe [<group>] <feed>', 'unsubscribe <feed>', 'list', 'add-payer <keypair>', 'remove-payer <pubkey>' or 'payers'".to_string(),
+        };
+        writer.write_all(response.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+async fn subscribe(group: &FeedGroup, feed: &str) -> String {
+    match group.subscribe(feed).await {
+        Ok(true) => "ok".to_string(),
+        Ok(false) => "unchanged".to_string(),
+        Err(e) => format!("error: {}", e),
+    }
+}
@@ -1,5 +1,14 @@
+use crate::blockhash_cache::BlockhashRefresh;
+use crate::compute_budget::{ComputeBudgetConfig, UnitPrice};
+use crate::pipeline::PipelineConfig;
+use crate::preflight::PreflightConfig;
+use crate::publish_policy::{PublishPolicy, PublishPolicyConfig};
+use crate::reconnect::ReconnectPolicy;
+use crate::retry::RetryPolicy;
 use clap::{Parser, ValueEnum};
 use solana_sdk::signature::Keypair;
+use std::str::FromStr;
+use std::time::Duration;
 use tracing::warn;
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -25,13 +34,133 @@ impl std::fmt::Display for ChannelType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DeliveryFormat {
+    #[value(name = "json")]
+    Json,
+    #[value(name = "binary")]
+    Binary,
+}
+
+impl std::fmt::Display for DeliveryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeliveryFormat::Json => write!(f, "json"),
+            DeliveryFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TlsBackend {
+    #[value(name = "native")]
+    Native,
+    #[value(name = "rustls")]
+    Rustls,
+}
+
+impl std::fmt::Display for TlsBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TlsBackend::Native => write!(f, "native"),
+            TlsBackend::Rustls => write!(f, "rustls"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PayerSelection {
+    #[value(name = "round-robin")]
+    RoundRobin,
+    #[value(name = "feed-shard")]
+    FeedShard,
+}
+
+impl std::fmt::Display for PayerSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PayerSelection::RoundRobin => write!(f, "round-robin"),
+            PayerSelection::FeedShard => write!(f, "feed-shard"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PreflightMode {
+    #[value(name = "off")]
+    Off,
+    /// Send with the RPC's preflight checks, which reject a failing transaction.
+    #[value(name = "preflight")]
+    Preflight,
+    /// Run `simulateTransaction` first and log the outcome, then send as usual.
+    #[value(name = "simulate")]
+    Simulate,
+}
+
+impl std::fmt::Display for PreflightMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PreflightMode::Off => write!(f, "off"),
+            PreflightMode::Preflight => write!(f, "preflight"),
+            PreflightMode::Simulate => write!(f, "simulate"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BlockhashRefreshMode {
+    #[value(name = "poll")]
+    Poll,
+    #[value(name = "subscribe")]
+    Subscribe,
+}
+
+impl std::fmt::Display for BlockhashRefreshMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlockhashRefreshMode::Poll => write!(f, "poll"),
+            BlockhashRefreshMode::Subscribe => write!(f, "subscribe"),
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    #[arg(
+        long,
+        help = "TOML file with providers, feed groups, cluster, payer and publish policy. Command line arguments and environment variables take precedence"
+    )]
+    pub config: Option<String>,
     #[arg(long, help = "Private key for the Solana wallet")]
     pub private_key: Option<String>,
-    #[arg(long, help = "Authorization header for the WebSocket connection")]
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Private keys of additional payers that sign update transactions"
+    )]
+    pub payer_keys: Vec<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "How transactions are spread over payers (default: round-robin)"
+    )]
+    pub payer_selection: Option<PayerSelection>,
+    #[arg(
+        long,
+        help = "Balance in lamports below which a payer is reported as low (default: 10000000)"
+    )]
+    pub min_payer_balance: Option<u64>,
+    #[arg(
+        long,
+        help = "Authorization header for WebSocket connections without a more specific credential"
+    )]
     pub auth_header: Option<String>,
+    #[arg(
+        long = "credential",
+        help = "Credential for a provider or URL as <pyth-lazer|stork|url>=<bearer:token|basic:token|basic:user:password|query:name=value|header:value|token>, can be repeated"
+    )]
+    pub credentials: Vec<String>,
 
     #[arg(
         long,
@@ -48,15 +177,234 @@ pub struct Args {
     )]
     pub ws_urls: Vec<String>,
 
+    #[arg(
+        long,
+        value_enum,
+        help = "Check the first transaction of each feed with preflight or simulateTransaction (default: off)"
+    )]
+    pub preflight: Option<PreflightMode>,
+    #[arg(
+        long,
+        help = "Also check every Nth transaction when --preflight is set, 0 disables (default: 0)"
+    )]
+    pub preflight_sample_every: Option<u64>,
+    #[arg(
+        long,
+        help = "Build and sign transactions but write them out instead of sending them"
+    )]
+    pub dry_run: bool,
+    #[arg(
+        long,
+        help = "File dry-run transactions are written to (default: stdout)"
+    )]
+    pub dry_run_output: Option<String>,
     #[arg(long, help = "Solana cluster URL")]
     pub cluster: Option<String>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Additional RPC URLs of the same cluster that every transaction is also sent to"
+    )]
+    pub send_urls: Vec<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Refresh the blockhash by polling the block height or following slot notifications (default: poll)"
+    )]
+    pub blockhash_refresh: Option<BlockhashRefreshMode>,
+    #[arg(
+        long,
+        help = "PubSub WebSocket URL of the cluster, derived from --cluster by default"
+    )]
+    pub cluster_ws_url: Option<String>,
+    #[arg(
+        long,
+        help = "Pause sending while the cached blockhash is older than this many seconds (default: 60)"
+    )]
+    pub max_blockhash_age_secs: Option<u64>,
     #[arg(long, help = "Comma-separated list of price feeds")]
     pub price_feeds: Option<String>,
     #[arg(
         long,
-        help = "Channel of the WebSocket to subscribe to (real_time, fixed_rate@1ms, fixed_rate@50ms, fixed_rate@200ms)"
+        help = "Channel of the WebSocket to subscribe to (real_time, fixed_rate@1ms, fixed_rate@50ms, fixed_rate@200ms), for feed groups without a channel in the config file"
     )]
     pub channel: Option<ChannelType>,
+    #[arg(
+        long,
+        help = "Format the provider should deliver price updates in (json, binary). Binary is only supported by Pyth Lazer"
+    )]
+    pub delivery_format: Option<DeliveryFormat>,
+    #[arg(
+        long,
+        help = "Connect to all WebSocket URLs at once and merge their updates instead of failing over"
+    )]
+    pub redundant: bool,
+    #[arg(
+        long,
+        help = "Feeds one provider connection subscribes to, more are spread over extra connections, 0 is unlimited (default: 20 for Pyth Lazer, unlimited for Stork)"
+    )]
+    pub max_feeds_per_connection: Option<usize>,
+    #[arg(
+        long,
+        help = "Path of a Unix socket accepting 'subscribe <feed>' and 'unsubscribe <feed>' commands"
+    )]
+    pub admin_socket: Option<String>,
+    #[arg(
+        long,
+        help = "Proxy for provider connections (http://, socks5:// or socks5h://). Defaults to HTTPS_PROXY or ALL_PROXY"
+    )]
+    pub proxy: Option<String>,
+    #[arg(long, value_enum, help = "TLS implementation for provider connections")]
+    pub tls_backend: Option<TlsBackend>,
+    #[arg(long, help = "PEM file with extra root certificates to trust")]
+    pub tls_ca_file: Option<String>,
+    #[arg(
+        long = "tls-pin",
+        help = "Certificate pin for a provider host as <host>=spki:<base64 sha256> or <host>=cert:<hex sha256>, can be repeated"
+    )]
+    pub tls_pins: Vec<String>,
+    #[arg(
+        long,
+        help = "Seconds to wait for in-flight transactions on shutdown (default: 10)"
+    )]
+    pub shutdown_timeout_secs: Option<u64>,
+    #[arg(
+        long,
+        help = "Seconds a feed may go without updates before it is resubscribed, 0 disables (default: 60)"
+    )]
+    pub feed_stale_secs: Option<u64>,
+    #[arg(
+        long,
+        help = "Reconnect when feeds stay silent after being resubscribed instead of only logging them"
+    )]
+    pub feed_stale_reconnect: bool,
+
+    #[arg(
+        long,
+        help = "Delay before the first reconnect attempt, in milliseconds"
+    )]
+    pub reconnect_initial_delay_ms: Option<u64>,
+    #[arg(long, help = "Upper bound for the reconnect delay, in milliseconds")]
+    pub reconnect_max_delay_ms: Option<u64>,
+    #[arg(
+        long,
+        help = "Factor the reconnect delay grows by after each failed attempt"
+    )]
+    pub reconnect_multiplier: Option<f64>,
+    #[arg(
+        long,
+        help = "Fraction of the reconnect delay that is randomised, between 0 and 1"
+    )]
+    pub reconnect_jitter: Option<f64>,
+    #[arg(
+        long,
+        help = "Attempts per price update transaction including the first, 1 disables retries (default: 3)"
+    )]
+    pub max_send_attempts: Option<u32>,
+    #[arg(
+        long,
+        help = "Delay before re-sending a failed or expired transaction (default: 250)"
+    )]
+    pub retry_delay_ms: Option<u64>,
+    #[arg(
+        long,
+        help = "Feeds that can have an update waiting to be sent at once (default: 1024)"
+    )]
+    pub send_queue_capacity: Option<usize>,
+    #[arg(
+        long,
+        help = "Transactions sent to the RPC at the same time (default: 16)"
+    )]
+    pub max_concurrent_sends: Option<usize>,
+    #[arg(
+        long,
+        help = "Priority fee in micro-lamports per compute unit, or 'dynamic' to follow recent fees for the feed accounts"
+    )]
+    pub compute_unit_price: Option<String>,
+    #[arg(
+        long,
+        help = "Highest compute unit price the dynamic priority fee may reach (default: 100000)"
+    )]
+    pub max_compute_unit_price: Option<u64>,
+    #[arg(
+        long,
+        help = "Compute unit limit requested per price update in a transaction"
+    )]
+    pub compute_unit_limit: Option<u32>,
+    #[arg(
+        long,
+        help = "Smallest price move in basis points since the last published price that is published, 0 publishes every tick (default: 0)"
+    )]
+    pub publish_deviation_bps: Option<u32>,
+    #[arg(
+        long,
+        help = "Publish a feed at least this often even if its price didn't move, 0 disables (default: 60)"
+    )]
+    pub publish_heartbeat_secs: Option<u64>,
+    #[arg(
+        long = "publish-policy",
+        help = "Per-feed publish policy as <feed>=<deviation_bps>:<heartbeat_secs>, can be repeated"
+    )]
+    pub publish_policies: Vec<String>,
+}
+
+fn env_or<T: FromStr>(name: &str, cli_value: Option<T>) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid {} value: '{}'", name, value))
+        })
+        .or(cli_value)
+}
+
+/// Parses the `ValueEnum` in env var `name` case-insensitively, falling back to `cli_value`.
+fn env_enum<T: ValueEnum>(name: &str, cli_value: Option<T>) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            T::from_str(value.trim(), true).unwrap_or_else(|_| {
+                let accepted = T::value_variants()
+                    .iter()
+                    .filter_map(|variant| variant.to_possible_value())
+                    .map(|value| value.get_name().to_string())
+                    .collect::<Vec<_>>();
+                panic!(
+                    "Invalid {} value: '{}'. Accepted values: {}",
+                    name,
+                    value,
+                    accepted.join(", ")
+                )
+            })
+        })
+        .or(cli_value)
+}
+
+/// A flag set by env var `name` to `1` or `true`. Any other value clears it, and without the
+/// env var `cli_value` is used.
+fn env_bool(name: &str, cli_value: bool) -> bool {
+    std::env::var(name)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(cli_value)
+}
+
+/// A comma-separated list in env var `name`, which replaces `cli_values` when set.
+fn env_list(name: &str, cli_values: Vec<String>) -> Vec<String> {
+    std::env::var(name)
+        .map(|values| {
+            values
+                .split(',')
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect()
+        })
+        .unwrap_or(cli_values)
+}
+
+pub fn get_config_path(cli_config: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_CONFIG").ok().or(cli_config)
 }
 
 pub fn get_ws_urls(cli_url: Option<String>, cli_urls: Vec<String>) -> Vec<String> {
@@ -83,20 +431,277 @@ pub fn get_ws_urls(cli_url: Option<String>, cli_urls: Vec<String>) -> Vec<String
     }
 }
 
-pub fn get_auth_header(cli_auth: Option<String>) -> String {
-    std::env::var("ORACLE_AUTH_HEADER")
+pub fn get_redundant(cli_redundant: bool) -> bool {
+    env_bool("ORACLE_REDUNDANT", cli_redundant)
+}
+
+/// `0` lifts the limit, including the provider's default one.
+pub fn get_max_feeds_per_connection(
+    cli_max_feeds: Option<usize>,
+    provider_default: Option<usize>,
+) -> Option<usize> {
+    env_or("ORACLE_MAX_FEEDS_PER_CONNECTION", cli_max_feeds)
+        .map(|max| (max > 0).then_some(max))
+        .unwrap_or(provider_default)
+}
+
+pub fn get_preflight(
+    cli_mode: Option<PreflightMode>,
+    cli_sample_every: Option<u64>,
+) -> PreflightConfig {
+    let mode = env_enum("ORACLE_PREFLIGHT", cli_mode).unwrap_or(PreflightMode::Off);
+    PreflightConfig {
+        mode,
+        sample_every: env_or("ORACLE_PREFLIGHT_SAMPLE_EVERY", cli_sample_every)
+            .filter(|&every| every > 0),
+    }
+}
+
+pub fn get_dry_run(cli_dry_run: bool) -> bool {
+    env_bool("ORACLE_DRY_RUN", cli_dry_run)
+}
+
+pub fn get_dry_run_output(cli_output: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_DRY_RUN_OUTPUT").ok().or(cli_output)
+}
+
+pub fn get_admin_socket(cli_socket: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_ADMIN_SOCKET").ok().or(cli_socket)
+}
+
+pub fn get_proxy(cli_proxy: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_PROXY")
         .ok()
-        .or(cli_auth)
-        .expect(
-            "ORACLE_AUTH_HEADER environment variable or --auth-header argument must be provided",
-        )
+        .or(cli_proxy)
+        .or_else(|| {
+            ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+        })
+        .filter(|proxy| !proxy.is_empty())
+}
+
+pub fn get_tls_backend(cli_backend: Option<TlsBackend>) -> TlsBackend {
+    env_enum("ORACLE_TLS_BACKEND", cli_backend).unwrap_or(TlsBackend::Native)
+}
+
+pub fn get_tls_ca_file(cli_ca_file: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_TLS_CA_FILE").ok().or(cli_ca_file)
+}
+
+/// Pins from `ORACLE_TLS_PINS` (comma-separated) replace the ones given on the command line.
+pub fn get_tls_pins(cli_pins: Vec<String>) -> Vec<String> {
+    env_list("ORACLE_TLS_PINS", cli_pins)
 }
 
-pub fn get_solana_cluster(cli_cluster: Option<String>) -> String {
+/// Extra payer keys from `ORACLE_PAYER_KEYS` (comma-separated) replace the ones given on the
+/// command line.
+pub fn get_payer_keys(cli_keys: Vec<String>) -> Vec<String> {
+    env_list("ORACLE_PAYER_KEYS", cli_keys)
+}
+
+pub fn get_payer_selection(cli_selection: Option<PayerSelection>) -> PayerSelection {
+    env_enum("ORACLE_PAYER_SELECTION", cli_selection).unwrap_or(PayerSelection::RoundRobin)
+}
+
+pub fn get_min_payer_balance(cli_min_balance: Option<u64>) -> u64 {
+    env_or("ORACLE_MIN_PAYER_BALANCE", cli_min_balance).unwrap_or(10_000_000)
+}
+
+pub fn get_shutdown_timeout(cli_timeout_secs: Option<u64>) -> Duration {
+    Duration::from_secs(env_or("ORACLE_SHUTDOWN_TIMEOUT_SECS", cli_timeout_secs).unwrap_or(10))
+}
+
+pub fn get_feed_stale_after(cli_stale_secs: Option<u64>) -> Option<Duration> {
+    match env_or("ORACLE_FEED_STALE_SECS", cli_stale_secs).unwrap_or(60) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+pub fn get_feed_stale_reconnect(cli_reconnect: bool) -> bool {
+    env_bool("ORACLE_FEED_STALE_RECONNECT", cli_reconnect)
+}
+
+pub fn get_reconnect_policy(
+    cli_initial_delay_ms: Option<u64>,
+    cli_max_delay_ms: Option<u64>,
+    cli_multiplier: Option<f64>,
+    cli_jitter: Option<f64>,
+) -> ReconnectPolicy {
+    let default = ReconnectPolicy::default();
+    ReconnectPolicy {
+        initial_delay: env_or("ORACLE_RECONNECT_INITIAL_DELAY_MS", cli_initial_delay_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(default.initial_delay),
+        max_delay: env_or("ORACLE_RECONNECT_MAX_DELAY_MS", cli_max_delay_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(default.max_delay),
+        multiplier: env_or("ORACLE_RECONNECT_MULTIPLIER", cli_multiplier)
+            .inspect(|&multiplier: &f64| {
+                if !multiplier.is_finite() || multiplier < 1.0 {
+                    panic!(
+                        "Invalid reconnect multiplier: '{}'. Expected a number of at least 1",
+                        multiplier
+                    );
+                }
+            })
+            .unwrap_or(default.multiplier),
+        jitter: env_or("ORACLE_RECONNECT_JITTER", cli_jitter)
+            .inspect(|&jitter: &f64| {
+                if !(0.0..=1.0).contains(&jitter) {
+                    panic!(
+                        "Invalid reconnect jitter: '{}'. Expected a number between 0 and 1",
+                        jitter
+                    );
+                }
+            })
+            .unwrap_or(default.jitter),
+    }
+}
+
+pub fn get_retry_policy(cli_max_attempts: Option<u32>, cli_delay_ms: Option<u64>) -> RetryPolicy {
+    let default = RetryPolicy::default();
+    RetryPolicy {
+        max_attempts: env_or("ORACLE_MAX_SEND_ATTEMPTS", cli_max_attempts)
+            .unwrap_or(default.max_attempts)
+            .max(1),
+        delay: env_or("ORACLE_RETRY_DELAY_MS", cli_delay_ms)
+            .map(Duration::from_millis)
+            .unwrap_or(default.delay),
+    }
+}
+
+pub fn get_pipeline_config(
+    cli_queue_capacity: Option<usize>,
+    cli_max_concurrent_sends: Option<usize>,
+) -> PipelineConfig {
+    let default = PipelineConfig::default();
+    PipelineConfig {
+        queue_capacity: env_or("ORACLE_SEND_QUEUE_CAPACITY", cli_queue_capacity)
+            .unwrap_or(default.queue_capacity),
+        max_concurrent_sends: env_or("ORACLE_MAX_CONCURRENT_SENDS", cli_max_concurrent_sends)
+            .unwrap_or(default.max_concurrent_sends),
+    }
+}
+
+pub fn get_compute_budget(
+    cli_unit_price: Option<String>,
+    cli_max_unit_price: Option<u64>,
+    cli_unit_limit: Option<u32>,
+) -> ComputeBudgetConfig {
+    let unit_price = std::env::var("ORACLE_COMPUTE_UNIT_PRICE")
+        .ok()
+        .or(cli_unit_price)
+        .map(|price| {
+            if price.trim().eq_ignore_ascii_case("dynamic") {
+                UnitPrice::Dynamic {
+                    max: env_or("ORACLE_MAX_COMPUTE_UNIT_PRICE", cli_max_unit_price)
+                        .unwrap_or(100_000),
+                }
+            } else {
+                UnitPrice::Fixed(price.trim().parse().unwrap_or_else(|_| {
+                    panic!(
+                        "Invalid compute unit price: '{}'. Expected micro-lamports or 'dynamic'",
+                        price
+                    )
+                }))
+            }
+        });
+    ComputeBudgetConfig {
+        unit_price,
+        unit_limit_per_update: env_or("ORACLE_COMPUTE_UNIT_LIMIT", cli_unit_limit),
+    }
+}
+
+/// Per-feed policies from `ORACLE_PUBLISH_POLICIES` (comma-separated) replace the ones given on
+/// the command line.
+pub fn get_publish_policy(
+    cli_deviation_bps: Option<u32>,
+    cli_heartbeat_secs: Option<u64>,
+    cli_policies: Vec<String>,
+) -> PublishPolicyConfig {
+    let heartbeat_secs = env_or("ORACLE_PUBLISH_HEARTBEAT_SECS", cli_heartbeat_secs).unwrap_or(60);
+    let default = PublishPolicy {
+        deviation_bps: env_or("ORACLE_PUBLISH_DEVIATION_BPS", cli_deviation_bps).unwrap_or(0),
+        heartbeat: (heartbeat_secs > 0).then(|| Duration::from_secs(heartbeat_secs)),
+    };
+    let policies = env_list("ORACLE_PUBLISH_POLICIES", cli_policies);
+    PublishPolicyConfig::parse(default, &policies)
+        .unwrap_or_else(|e| panic!("Invalid publish policy: {}", e))
+}
+
+pub fn get_auth_header(cli_auth: Option<String>) -> Option<String> {
+    std::env::var("ORACLE_AUTH_HEADER").ok().or(cli_auth)
+}
+
+/// Credentials from `ORACLE_CREDENTIALS` (comma-separated) replace the ones given on the command
+/// line.
+pub fn get_credentials(cli_credentials: Vec<String>) -> Vec<String> {
+    env_list("ORACLE_CREDENTIALS", cli_credentials)
+}
+
+/// `ORACLE_SOLANA_CLUSTER` is accepted too, it is what deployments started by the supervisor
+/// script set. With a config file the cluster must be given, falling back to devnet could
+/// push mainnet prices to the wrong cluster.
+pub fn get_solana_cluster(cli_cluster: Option<String>, config_file: bool) -> String {
     std::env::var("SOLANA_CLUSTER")
+        .or_else(|_| std::env::var("ORACLE_SOLANA_CLUSTER"))
         .ok()
         .or(cli_cluster)
-        .unwrap_or_else(|| "https://devnet.magicblock.app/".to_string())
+        .unwrap_or_else(|| {
+            if config_file {
+                panic!("The config file, SOLANA_CLUSTER or --cluster must set the cluster");
+            }
+            "https://devnet.magicblock.app/".to_string()
+        })
+}
+
+/// The cluster URL followed by the extra send endpoints from `ORACLE_SEND_URLS`
+/// (comma-separated) or `--send-urls`.
+pub fn get_rpc_urls(cluster_url: &str, cli_send_urls: Vec<String>) -> Vec<String> {
+    let send_urls = env_list("ORACLE_SEND_URLS", cli_send_urls);
+    let mut urls = vec![cluster_url.to_string()];
+    for url in send_urls {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+pub fn get_blockhash_refresh(
+    cli_mode: Option<BlockhashRefreshMode>,
+    cli_ws_url: Option<String>,
+    cluster_url: &str,
+) -> BlockhashRefresh {
+    let mode = env_enum("ORACLE_BLOCKHASH_REFRESH", cli_mode).unwrap_or(BlockhashRefreshMode::Poll);
+    match mode {
+        BlockhashRefreshMode::Poll => BlockhashRefresh::Poll,
+        BlockhashRefreshMode::Subscribe => BlockhashRefresh::Subscribe {
+            url: std::env::var("ORACLE_CLUSTER_WS_URL")
+                .ok()
+                .or(cli_ws_url)
+                .unwrap_or_else(|| pubsub_url(cluster_url)),
+        },
+    }
+}
+
+pub fn get_max_blockhash_age(cli_max_age_secs: Option<u64>) -> Duration {
+    Duration::from_secs(env_or("ORACLE_MAX_BLOCKHASH_AGE_SECS", cli_max_age_secs).unwrap_or(60))
+}
+
+/// The PubSub URL Solana RPC nodes serve next to `rpc_url`: the WebSocket scheme, and the next
+/// port when one is given explicitly.
+fn pubsub_url(rpc_url: &str) -> String {
+    let mut url =
+        url::Url::parse(rpc_url).unwrap_or_else(|_| panic!("Invalid cluster URL: '{}'", rpc_url));
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    let _ = url.set_scheme(scheme);
+    if let Some(port) = url.port() {
+        let _ = url.set_port(Some(port + 1));
+    }
+    url.to_string()
 }
 
 pub fn get_price_feeds(cli_feeds: Option<String>) -> Vec<String> {
@@ -116,27 +721,78 @@ pub fn get_private_key(cli_key: Option<String>) -> String {
         .unwrap_or(Keypair::new().to_base58_string())
 }
 
-pub fn get_channel(cli_channel: Option<ChannelType>) -> String {
-    let valid_values = ChannelType::value_variants()
-        .iter()
-        .map(|v| v.to_string())
-        .collect::<Vec<_>>();
+/// The channel of a feed group. A channel the config file sets for the group or its provider
+/// wins over `ORACLE_CHANNEL` and `--channel`, which apply to every group, since groups are
+/// split by the channel their feeds need.
+pub fn get_channel(
+    cli_channel: Option<ChannelType>,
+    file_channel: Option<ChannelType>,
+    feed_group: &str,
+) -> String {
+    let process_channel = env_enum("ORACLE_CHANNEL", cli_channel).map(|c| c.to_string());
 
-    std::env::var("ORACLE_CHANNEL")
-        .map(|env_channel| {
-            ChannelType::value_variants()
-                .iter()
-                .find(|variant| variant.to_string().eq_ignore_ascii_case(&env_channel))
-                .map(|v| v.to_string())
-                .unwrap_or_else(|| {
-                    panic!(
-                        "Invalid ORACLE_CHANNEL value: '{}'. Accepted values: {}",
-                        env_channel,
-                        valid_values.join(", ")
-                    )
-                })
-        })
-        .ok()
-        .or(cli_channel.map(|c| c.to_string()))
-        .unwrap_or_else(|| ChannelType::FixedRate50ms.to_string())
+    match (file_channel.map(|c| c.to_string()), process_channel) {
+        (Some(file_channel), Some(process_channel)) => {
+            if file_channel != process_channel {
+                warn!(
+                    feed_group,
+                    channel = %file_channel,
+                    ignored_channel = %process_channel,
+                    "Config file channel overrides ORACLE_CHANNEL/--channel for this feed group"
+                );
+            }
+            file_channel
+        }
+        (Some(channel), None) | (None, Some(channel)) => channel,
+        (None, None) => ChannelType::FixedRate50ms.to_string(),
+    }
+}
+
+pub fn get_delivery_format(cli_format: Option<DeliveryFormat>) -> DeliveryFormat {
+    env_enum("ORACLE_DELIVERY_FORMAT", cli_format).unwrap_or(DeliveryFormat::Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_enum_ignores_case_and_prefers_env() {
+        std::env::set_var("ORACLE_TEST_ENV_ENUM", " Fixed_Rate@200ms ");
+        let channel = env_enum("ORACLE_TEST_ENV_ENUM", Some(ChannelType::RealTime));
+        assert_eq!(channel.unwrap().to_string(), "fixed_rate@200ms");
+        assert_eq!(
+            env_enum("ORACLE_TEST_ENV_ENUM_UNSET", Some(TlsBackend::Rustls)),
+            Some(TlsBackend::Rustls)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Accepted values: native, rustls")]
+    fn env_enum_lists_accepted_values() {
+        std::env::set_var("ORACLE_TEST_ENV_ENUM_INVALID", "openssl");
+        env_enum::<TlsBackend>("ORACLE_TEST_ENV_ENUM_INVALID", None);
+    }
+
+    #[test]
+    fn env_bool_accepts_1_and_true() {
+        for (value, expected) in [("1", true), ("TRUE", true), ("0", false), ("yes", false)] {
+            std::env::set_var("ORACLE_TEST_ENV_BOOL", value);
+            assert_eq!(
+                env_bool("ORACLE_TEST_ENV_BOOL", !expected),
+                expected,
+                "{}",
+                value
+            );
+        }
+        assert!(env_bool("ORACLE_TEST_ENV_BOOL_UNSET", true));
+    }
+
+    #[test]
+    fn env_list_replaces_cli_values() {
+        std::env::set_var("ORACLE_TEST_ENV_LIST", " a, ,b ,");
+        let cli = vec!["c".to_string()];
+        assert_eq!(env_list("ORACLE_TEST_ENV_LIST", cli.clone()), ["a", "b"]);
+        assert_eq!(env_list("ORACLE_TEST_ENV_LIST_UNSET", cli), ["c"]);
+    }
 }
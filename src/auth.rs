@@ -0,0 +1,324 @@
+use crate::types::ChainPusher;
+use base64::Engine;
+use std::collections::HashMap;
+use std::error::Error;
+use url::Url;
+
+/// How a provider connection authenticates.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+    None,
+    /// Sent as the `Authorization` header exactly as configured.
+    Header(String),
+    /// `Authorization: Bearer <token>`, used by Pyth Lazer.
+    Bearer(String),
+    /// `Authorization: Basic <token>` with an already encoded token, used by Stork.
+    Basic(String),
+    /// Token passed as a query parameter of the WebSocket URL.
+    QueryParam {
+        name: String,
+        value: String,
+    },
+}
+
+impl Auth {
+    /// Value of the `Authorization` header, if this scheme sends one.
+    pub fn authorization(&self) -> Option<String> {
+        match self {
+            Auth::Header(value) => Some(value.clone()),
+            Auth::Bearer(token) => Some(format!("Bearer {}", token)),
+            Auth::Basic(token) => Some(format!("Basic {}", token)),
+            Auth::None | Auth::QueryParam { .. } => None,
+        }
+    }
+
+    pub fn apply_to_url(&self, url: &mut Url) {
+        if let Auth::QueryParam { name, value } = self {
+            url.query_pairs_mut().append_pair(name, value);
+        }
+    }
+}
+
+// Credentials end up in logs through Debug, so only the scheme is shown.
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Auth::None => write!(f, "None"),
+            Auth::Header(_) => write!(f, "Header"),
+            Auth::Bearer(_) => write!(f, "Bearer"),
+            Auth::Basic(_) => write!(f, "Basic"),
+            Auth::QueryParam { name, .. } => write!(f, "QueryParam({})", name),
+        }
+    }
+}
+
+/// A configured credential. A bare token is sent with the provider's own scheme.
+#[derive(Clone)]
+enum Credential {
+    Token(String),
+    Auth(Auth),
+}
+
+impl Credential {
+    /// Parses `bearer:<token>`, `basic:<token>`, `basic:<user>:<password>`,
+    /// `query:<name>=<value>`, `header:<value>` or a bare token.
+    fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        let Some((scheme, rest)) = value.split_once(':') else {
+            return Ok(Credential::Token(value.to_string()));
+        };
+        let auth = match scheme {
+            "bearer" => Auth::Bearer(rest.to_string()),
+            "basic" if rest.contains(':') => {
+                Auth::Basic(base64::engine::general_purpose::STANDARD.encode(rest))
+            }
+            "basic" => Auth::Basic(rest.to_string()),
+            "query" => {
+                let (name, value) = rest
+                    .split_once('=')
+                    .ok_or("Expected query:<name>=<value>")?;
+                Auth::QueryParam {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                }
+            }
+            "header" => Auth::Header(rest.to_string()),
+            _ => return Ok(Credential::Token(value.to_string())),
+        };
+        Ok(Credential::Auth(auth))
+    }
+}
+
+/// Credentials for every provider connection, looked up by URL first, then by provider name,
+/// then falling back to the `--auth-header` value.
+pub struct Credentials {
+    by_provider: HashMap<String, Credential>,
+    /// Every way each `<url>=<credential>` entry can be split, since a URL may contain `=`
+    /// itself. The URL being connected to picks the reading that applies.
+    by_url: Vec<Vec<(String, Credential)>>,
+    fallback: Option<String>,
+}
+
+impl Credentials {
+    /// Parses `<target>=<credential>` entries, where the target is a WebSocket URL or a provider
+    /// name (`pyth-lazer`, `stork`).
+    pub fn parse(entries: &[String], fallback: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let mut by_provider = HashMap::new();
+        let mut by_url = vec![];
+        for entry in entries {
+            let (target, credential) = entry
+                .split_once('=')
+                .ok_or("Expected <url or provider>=<credential>")?;
+            if !target.contains("://") {
+                by_provider.insert(
+                    target.trim().to_string(),
+                    Credential::parse(credential.trim())?,
+                );
+                continue;
+            }
+            let readings: Vec<(String, Credential)> = entry
+                .match_indices('=')
+                .filter_map(|(i, _)| {
+                    let credential = Credential::parse(entry[i + 1..].trim()).ok()?;
+                    Some((entry[..i].trim().to_string(), credential))
+                })
+                .collect();
+            if readings.is_empty() {
+                return Err(format!("Invalid credential for {}", target.trim()).into());
+            }
+            by_url.push(readings);
+        }
+        Ok(Credentials {
+            by_provider,
+            by_url,
+            fallback,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_provider.is_empty() && self.by_url.is_empty() && self.fallback.is_none()
+    }
+
+    pub fn resolve(&self, url: &str, chain_pusher: &dyn ChainPusher) -> Auth {
+        self.resolve_for(url, chain_pusher.provider_name(), |token| {
+            chain_pusher.token_auth(token)
+        })
+    }
+
+    /// Resolves the credential of `url` for `provider`, sending bare tokens with `token_auth`.
+    fn resolve_for(&self, url: &str, provider: &str, token_auth: impl Fn(String) -> Auth) -> Auth {
+        // later entries replace earlier ones, as they do for providers
+        let by_url = self
+            .by_url
+            .iter()
+            .rev()
+            .flatten()
+            .find(|(target, _)| target == url)
+            .map(|(_, credential)| credential);
+        match by_url.or_else(|| self.by_provider.get(provider)) {
+            Some(Credential::Auth(auth)) => auth.clone(),
+            Some(Credential::Token(token)) => token_auth(token.clone()),
+            None => self.fallback.clone().map_or(Auth::None, Auth::Header),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "wss://pyth-lazer.example.com/v1/stream";
+
+    fn credentials(entries: &[&str], fallback: Option<&str>) -> Credentials {
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        Credentials::parse(&entries, fallback.map(str::to_string)).unwrap()
+    }
+
+    fn resolve(credentials: &Credentials, url: &str, provider: &str) -> Auth {
+        credentials.resolve_for(url, provider, Auth::Bearer)
+    }
+
+    #[test]
+    fn url_target_may_contain_equals_in_its_query() {
+        let url = "wss://pyth-lazer.example.com/v1/stream?region=eu&tier=pro";
+        let credentials = credentials(&[&format!("{}=query:token=abc", url)], None);
+        assert_eq!(
+            resolve(&credentials, url, "pyth-lazer"),
+            Auth::QueryParam {
+                name: "token".to_string(),
+                value: "abc".to_string(),
+            }
+        );
+        // shorter readings of the same entry name URLs that aren't connected to
+        assert_eq!(
+            resolve(
+                &credentials,
+                "wss://pyth-lazer.example.com/v1/stream?region",
+                "stork"
+            ),
+            Auth::Bearer("eu&tier=pro=query:token=abc".to_string())
+        );
+    }
+
+    #[test]
+    fn base64_padding_stays_in_the_token() {
+        let credentials = credentials(
+            &[
+                "pyth-lazer=dG9rZW4=",
+                &format!("{}=basic:dXNlcjpwYXNz==", URL),
+            ],
+            None,
+        );
+        assert_eq!(
+            resolve(&credentials, "wss://other.example.com", "pyth-lazer"),
+            Auth::Bearer("dG9rZW4=".to_string())
+        );
+        assert_eq!(
+            resolve(&credentials, URL, "pyth-lazer"),
+            Auth::Basic("dXNlcjpwYXNz==".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_every_scheme() {
+        let credentials = credentials(
+            &[
+                "wss://a.example.com=bearer:abc",
+                "wss://b.example.com=basic:user:pass",
+                "wss://c.example.com=header:Token abc",
+                "wss://d.example.com=abc",
+                "wss://e.example.com=other:abc",
+            ],
+            None,
+        );
+        let resolve = |url| resolve(&credentials, url, "stork");
+        assert_eq!(
+            resolve("wss://a.example.com"),
+            Auth::Bearer("abc".to_string())
+        );
+        assert_eq!(
+            resolve("wss://b.example.com"),
+            Auth::Basic("dXNlcjpwYXNz".to_string())
+        );
+        assert_eq!(
+            resolve("wss://c.example.com"),
+            Auth::Header("Token abc".to_string())
+        );
+        assert_eq!(
+            resolve("wss://d.example.com"),
+            Auth::Bearer("abc".to_string())
+        );
+        assert_eq!(
+            resolve("wss://e.example.com"),
+            Auth::Bearer("other:abc".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let parse = |entry: &str| Credentials::parse(&[entry.to_string()], None);
+        assert!(parse("pyth-lazer").is_err());
+        assert!(parse("pyth-lazer=query:token").is_err());
+        assert!(parse(&format!("{}=query:token", URL)).is_err());
+    }
+
+    #[test]
+    fn provider_name_and_url_targets_are_told_apart() {
+        let credentials = credentials(&["pyth-lazer=abc", &format!("{}=def", URL)], None);
+        assert_eq!(
+            resolve(&credentials, URL, "pyth-lazer"),
+            Auth::Bearer("def".to_string())
+        );
+        assert_eq!(
+            resolve(&credentials, "wss://other.example.com", "pyth-lazer"),
+            Auth::Bearer("abc".to_string())
+        );
+        assert_eq!(
+            resolve(&credentials, "wss://other.example.com", "stork"),
+            Auth::None
+        );
+    }
+
+    #[test]
+    fn resolves_url_then_provider_then_fallback() {
+        let credentials = credentials(
+            &[
+                &format!("{}=bearer:first", URL),
+                "pyth-lazer=provider",
+                &format!("{}=bearer:url", URL),
+            ],
+            Some("Bearer fallback"),
+        );
+        assert_eq!(
+            resolve(&credentials, URL, "pyth-lazer"),
+            Auth::Bearer("url".to_string())
+        );
+        assert_eq!(
+            resolve(&credentials, "wss://other.example.com", "pyth-lazer"),
+            Auth::Bearer("provider".to_string())
+        );
+        assert_eq!(
+            resolve(&credentials, "wss://other.example.com", "stork"),
+            Auth::Header("Bearer fallback".to_string())
+        );
+        assert!(!credentials.is_empty());
+        assert!(Credentials::parse(&[], None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn debug_never_shows_the_secret() {
+        let secrets = [
+            Auth::Header("Bearer s3cret".to_string()),
+            Auth::Bearer("s3cret".to_string()),
+            Auth::Basic("s3cret".to_string()),
+            Auth::QueryParam {
+                name: "token".to_string(),
+                value: "s3cret".to_string(),
+            },
+        ];
+        for auth in secrets {
+            let debug = format!("{:?}", auth);
+            assert!(!debug.contains("s3cret"), "{} leaks the secret", debug);
+            assert!(!format!("{:#?}", Some(auth)).contains("s3cret"));
+        }
+    }
+}
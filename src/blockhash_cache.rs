@@ -1,64 +1,294 @@
-use solana_client::nonblocking;
+use crate::reconnect::ReconnectPolicy;
+use crate::rpc_endpoints::{redact_url, RpcEndpoints};
+use arc_swap::ArcSwap;
+use futures_util::StreamExt;
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_sdk::hash::Hash;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
 
-#[derive(Clone)]
-pub struct BlockhashCache {
-    inner: Arc<RwLock<CacheData>>,
-    client: Arc<nonblocking::rpc_client::RpcClient>,
+/// About one slot, so the block height is rarely more than a block behind.
+const POLL_INTERVAL: Duration = Duration::from_millis(400);
+/// A blockhash is valid for 150 blocks. It is replaced once fewer than this many are left, so
+/// a transaction signed with it still has time to land.
+const MIN_REMAINING_BLOCKS: u64 = 100;
+/// A slot subscription without notifications for this long is treated as dropped.
+const SLOT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to poll after the slot subscription dropped before subscribing again.
+const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(30);
+/// Attempts at fetching the first blockhash before giving up on startup.
+const STARTUP_ATTEMPTS: u32 = 10;
+
+/// What drives blockhash refreshes.
+#[derive(Debug, Clone)]
+pub enum BlockhashRefresh {
+    /// Polls the block height every 400ms.
+    Poll,
+    /// Follows slot notifications from the cluster's PubSub endpoint at `url`, falling back to
+    /// polling while the subscription is down.
+    Subscribe { url: String },
 }
 
-struct CacheData {
+/// A blockhash and the last block height at which transactions signed with it can land.
+#[derive(Debug, Clone, Copy)]
+pub struct SigningBlockhash {
+    pub hash: Hash,
+    pub last_valid_block_height: u64,
+}
+
+struct CachedBlockhash {
     blockhash: Hash,
-    timestamp: Instant,
-    counter: u8,
+    last_valid_block_height: u64,
+    fetched_at: Instant,
 }
 
-impl BlockhashCache {
-    pub async fn new(client: Arc<nonblocking::rpc_client::RpcClient>) -> Self {
-        let initial_blockhash = client.get_latest_blockhash().await.unwrap();
-        let inner = Arc::new(RwLock::new(CacheData {
-            blockhash: initial_blockhash,
-            timestamp: Instant::now(),
-            counter: 0,
-        }));
+#[derive(Debug, Clone, Copy)]
+pub struct BlockhashStats {
+    pub age: Duration,
+    pub remaining_blocks: u64,
+    pub refresh_failures: u64,
+}
 
-        let cache = Self { inner, client };
+/// The blockhash update transactions are signed with. Reads never lock: the current blockhash
+/// is swapped in whole by a background task that tracks the block height and replaces it
+/// before it gets close to expiring. When refreshes keep failing, the cache reports itself
+/// unhealthy so no more transactions are signed with a blockhash that can't land.
+#[derive(Clone)]
+pub struct BlockhashCache {
+    current: Arc<ArcSwap<CachedBlockhash>>,
+    block_height: Arc<AtomicU64>,
+    /// The block height as last reported by the RPC, which unlike `block_height` never runs
+    /// ahead of the cluster's.
+    synced_block_height: Arc<AtomicU64>,
+    client: Arc<RpcEndpoints>,
+    max_age: Duration,
+    refresh_failures: Arc<AtomicU64>,
+    consecutive_failures: Arc<AtomicU64>,
+}
 
-        cache.spawn_refresh_task();
+impl BlockhashCache {
+    /// Fetches the first blockhash, retrying with backoff, and starts refreshing it. Panics if
+    /// no endpoint answers after a few attempts.
+    pub async fn new(
+        client: Arc<RpcEndpoints>,
+        refresh: BlockhashRefresh,
+        max_age: Duration,
+    ) -> Self {
+        let backoff = ReconnectPolicy::default();
+        let mut attempt = 0;
+        let (blockhash, last_valid_block_height, block_height) = loop {
+            match Self::fetch_initial(&client).await {
+                Ok(initial) => break initial,
+                Err(e) if attempt + 1 < STARTUP_ATTEMPTS => {
+                    let delay = backoff.delay(attempt);
+                    warn!(
+                        attempt = attempt + 1,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        "Failed to fetch the initial blockhash, retrying"
+                    );
+                    time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => panic!(
+                    "Failed to fetch a blockhash after {} attempts: {}",
+                    STARTUP_ATTEMPTS, e
+                ),
+            }
+        };
+        let cache = Self {
+            current: Arc::new(ArcSwap::from_pointee(CachedBlockhash {
+                blockhash,
+                last_valid_block_height,
+                fetched_at: Instant::now(),
+            })),
+            block_height: Arc::new(AtomicU64::new(block_height)),
+            synced_block_height: Arc::new(AtomicU64::new(block_height)),
+            client,
+            max_age,
+            refresh_failures: Arc::new(AtomicU64::new(0)),
+            consecutive_failures: Arc::new(AtomicU64::new(0)),
+        };
+
+        tokio::spawn(cache.clone().run_refresh(refresh));
         cache
     }
 
-    fn spawn_refresh_task(&self) {
-        let inner = self.inner.clone();
-        let client = self.client.clone();
+    async fn fetch_initial(client: &RpcEndpoints) -> Result<(Hash, u64, u64), ClientError> {
+        let (blockhash, last_valid_block_height) = client.get_latest_blockhash().await?;
+        let block_height = client.get_block_height().await?;
+        Ok((blockhash, last_valid_block_height, block_height))
+    }
 
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_millis(100)).await;
+    async fn run_refresh(self, refresh: BlockhashRefresh) {
+        let BlockhashRefresh::Subscribe { url } = refresh else {
+            self.poll_until(None).await;
+            return;
+        };
+        loop {
+            if let Err(e) = self.follow_slots(&url).await {
+                warn!(
+                    url = %redact_url(&url),
+                    error = %e,
+                    "Slot subscription dropped, polling for blockhashes"
+                );
+            }
+            self.poll_until(Some(Instant::now() + RESUBSCRIBE_AFTER))
+                .await;
+        }
+    }
 
-                let should_refresh = {
-                    let cache = inner.read().await;
-                    cache.counter >= 10 || cache.timestamp.elapsed() > Duration::from_secs(60)
-                };
+    /// Polls the block height until `deadline`, or forever without one.
+    async fn poll_until(&self, deadline: Option<Instant>) {
+        while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            time::sleep(POLL_INTERVAL).await;
+            self.sync_block_height().await;
+            if self.needs_refresh() {
+                self.refresh().await;
+            }
+        }
+    }
+
+    /// Refreshes on slot notifications without asking the RPC for the block height in between.
+    /// Every slot is counted as a block, which overestimates the height when slots are skipped,
+    /// so the blockhash is only ever replaced early. Returns when the subscription drops.
+    async fn follow_slots(&self, url: &str) -> Result<(), Box<dyn Error>> {
+        let pubsub = PubsubClient::new(url).await?;
+        let (mut slots, _unsubscribe) = pubsub.slot_subscribe().await?;
+        info!(url = %redact_url(url), "Following slots for blockhash refreshes");
 
-                if should_refresh {
-                    if let Ok(new_blockhash) = client.get_latest_blockhash().await {
-                        let mut cache = inner.write().await;
-                        cache.blockhash = new_blockhash;
-                        cache.timestamp = Instant::now();
-                        cache.counter = 0;
-                    }
+        let mut last_slot = None;
+        loop {
+            let slot = match time::timeout(SLOT_TIMEOUT, slots.next()).await {
+                Ok(Some(info)) => info.slot,
+                Ok(None) => return Err("subscription closed".into()),
+                Err(_) => return Err("no slot notifications".into()),
+            };
+            if let Some(last_slot) = last_slot {
+                self.block_height
+                    .fetch_add(slot.saturating_sub(last_slot), Ordering::Relaxed);
+            }
+            last_slot = Some(slot);
+
+            if self.needs_refresh() {
+                self.refresh().await;
+                self.sync_block_height().await;
+            }
+        }
+    }
+
+    async fn sync_block_height(&self) {
+        if let Ok(block_height) = self.client.get_block_height().await {
+            self.block_height.store(block_height, Ordering::Relaxed);
+            self.synced_block_height
+                .store(block_height, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether the blockhash is close to expiring, or halfway to the age at which sends pause.
+    /// The age matters on its own when blocks are slow or the block height can't be fetched.
+    fn needs_refresh(&self) -> bool {
+        self.remaining_blocks() < MIN_REMAINING_BLOCKS || self.age() >= self.max_age / 2
+    }
+
+    /// Blocks left before the cached blockhash expires, as of the last known block height.
+    fn remaining_blocks(&self) -> u64 {
+        self.current
+            .load()
+            .last_valid_block_height
+            .saturating_sub(self.block_height.load(Ordering::Relaxed))
+    }
+
+    /// Fetches a new blockhash right away, for transactions that expired with the cached one.
+    /// Falls back to the cached blockhash if the RPC call fails.
+    pub async fn refresh(&self) -> Hash {
+        match self.client.get_latest_blockhash().await {
+            Ok((blockhash, last_valid_block_height)) => {
+                self.current.store(Arc::new(CachedBlockhash {
+                    blockhash,
+                    last_valid_block_height,
+                    fetched_at: Instant::now(),
+                }));
+                let failures = self.consecutive_failures.swap(0, Ordering::Relaxed);
+                if failures > 0 {
+                    info!(failures, "Blockhash refresh recovered");
                 }
+                blockhash
+            }
+            Err(e) => {
+                self.refresh_failures.fetch_add(1, Ordering::Relaxed);
+                let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    error = %e,
+                    consecutive_failures = failures,
+                    age_ms = self.age().as_millis() as u64,
+                    "Failed to refresh blockhash, keeping the cached one"
+                );
+                self.get_blockhash()
+            }
+        }
+    }
+
+    /// Time since the cached blockhash was fetched.
+    pub fn age(&self) -> Duration {
+        self.current.load().fetched_at.elapsed()
+    }
+
+    /// Whether transactions signed with the cached blockhash can still land: it is not older
+    /// than the configured maximum and the block height hasn't passed its last valid one.
+    pub fn is_healthy(&self) -> bool {
+        self.age() <= self.max_age && self.remaining_blocks() > 0
+    }
+
+    /// Returns once the blockhash is healthy, pausing the caller and refreshing it while it is
+    /// not.
+    pub async fn wait_until_healthy(&self) {
+        if self.is_healthy() {
+            return;
+        }
+        warn!(
+            age_ms = self.age().as_millis() as u64,
+            remaining_blocks = self.remaining_blocks(),
+            "Blockhash is stale, pausing sends until it is refreshed"
+        );
+        while !self.is_healthy() {
+            self.refresh().await;
+            self.sync_block_height().await;
+            if !self.is_healthy() {
+                time::sleep(POLL_INTERVAL).await;
             }
-        });
+        }
+        info!("Blockhash refreshed, resuming sends");
+    }
+
+    pub fn stats(&self) -> BlockhashStats {
+        BlockhashStats {
+            age: self.age(),
+            remaining_blocks: self.remaining_blocks(),
+            refresh_failures: self.refresh_failures.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn get_blockhash(&self) -> Hash {
+        self.current.load().blockhash
+    }
+
+    pub fn get_signing_blockhash(&self) -> SigningBlockhash {
+        let current = self.current.load();
+        SigningBlockhash {
+            hash: current.blockhash,
+            last_valid_block_height: current.last_valid_block_height,
+        }
     }
 
-    pub async fn get_blockhash(&self) -> Hash {
-        let mut cache = self.inner.write().await;
-        cache.counter = (cache.counter + 1) % 11;
-        cache.blockhash
+    /// The block height as last reported by the RPC. It stops advancing while the RPC can't be
+    /// reached.
+    pub fn synced_block_height(&self) -> u64 {
+        self.synced_block_height.load(Ordering::Relaxed)
     }
 }
@@ -0,0 +1,119 @@
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::{self, Duration};
+use tracing::{debug, warn};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// `getRecentPrioritizationFees` accepts at most this many accounts.
+const MAX_SAMPLED_ACCOUNTS: usize = 128;
+/// Percentile of recent fees paid for the feed accounts that dynamic pricing matches.
+const FEE_PERCENTILE: usize = 75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitPrice {
+    /// Micro-lamports per compute unit.
+    Fixed(u64),
+    /// Follows recent prioritization fees for the written feed accounts, capped at `max`.
+    Dynamic { max: u64 },
+}
+
+/// Compute budget instructions added to the update transactions of one cluster.
+#[derive(Debug, Clone, Default)]
+pub struct ComputeBudgetConfig {
+    pub unit_price: Option<UnitPrice>,
+    pub unit_limit_per_update: Option<u32>,
+}
+
+/// Builds the compute budget instructions for update transactions, sampling prioritization fees
+/// in the background when the unit price is dynamic.
+pub struct ComputeBudget {
+    config: ComputeBudgetConfig,
+    sampled_price: AtomicU64,
+    /// Feed accounts written so far, whose recent fees the dynamic price follows.
+    accounts: Mutex<HashSet<Pubkey>>,
+}
+
+impl ComputeBudget {
+    /// With a dynamic unit price, takes a first sample before returning so the first
+    /// transactions aren't priced at 0.
+    pub async fn spawn(config: ComputeBudgetConfig, rpc_client: Arc<RpcClient>) -> Arc<Self> {
+        let budget = Arc::new(ComputeBudget {
+            config,
+            sampled_price: AtomicU64::new(0),
+            accounts: Mutex::new(HashSet::new()),
+        });
+
+        if let Some(UnitPrice::Dynamic { max }) = budget.config.unit_price {
+            budget.sample(&rpc_client, max).await;
+            let sampler = budget.clone();
+            tokio::spawn(async move {
+                loop {
+                    time::sleep(SAMPLE_INTERVAL).await;
+                    sampler.sample(&rpc_client, max).await;
+                }
+            });
+        }
+        budget
+    }
+
+    /// Instructions to put in front of `updates` update instructions writing `feed_accounts`.
+    pub fn instructions(&self, updates: usize, feed_accounts: &[Pubkey]) -> Vec<Instruction> {
+        let mut ixs = vec![];
+        if let Some(limit) = self.config.unit_limit_per_update {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                limit.saturating_mul(updates as u32),
+            ));
+        }
+        match self.config.unit_price {
+            Some(UnitPrice::Fixed(price)) => {
+                ixs.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+            }
+            Some(UnitPrice::Dynamic { .. }) => {
+                self.accounts
+                    .lock()
+                    .unwrap()
+                    .extend(feed_accounts.iter().copied());
+                ixs.push(ComputeBudgetInstruction::set_compute_unit_price(
+                    self.sampled_price.load(Ordering::Relaxed),
+                ));
+            }
+            None => {}
+        }
+        ixs
+    }
+
+    async fn sample(&self, rpc_client: &RpcClient, max: u64) {
+        let accounts: Vec<Pubkey> = self
+            .accounts
+            .lock()
+            .unwrap()
+            .iter()
+            .take(MAX_SAMPLED_ACCOUNTS)
+            .copied()
+            .collect();
+        // before the first send there are no feed accounts yet, and without accounts the
+        // cluster's recent fees in general are returned
+        match rpc_client.get_recent_prioritization_fees(&accounts).await {
+            Ok(fees) => {
+                let mut fees: Vec<u64> = fees.iter().map(|fee| fee.prioritization_fee).collect();
+                if fees.is_empty() {
+                    return;
+                }
+                fees.sort_unstable();
+                let price = fees[(fees.len() - 1) * FEE_PERCENTILE / 100].min(max);
+                debug!(
+                    price,
+                    accounts = accounts.len(),
+                    "Sampled compute unit price"
+                );
+                self.sampled_price.store(price, Ordering::Relaxed);
+            }
+            Err(e) => warn!(error = ?e, "Failed to sample prioritization fees"),
+        }
+    }
+}
@@ -0,0 +1,578 @@
+use crate::args::{Args, BlockhashRefreshMode, ChannelType, DeliveryFormat, PayerSelection};
+use clap::ValueEnum;
+use serde::Deserialize;
+use solana_sdk::signature::Keypair;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A TOML config file passed with `--config`. It stands in for the command line arguments it
+/// sets, so `ORACLE_*` environment variables still override it, and it can describe several
+/// providers with their own feeds.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// The one cluster this process pushes to. Run one process per cluster to push to several.
+    cluster: Option<String>,
+    #[serde(default)]
+    send_urls: Vec<String>,
+    cluster_ws_url: Option<String>,
+    blockhash_refresh: Option<String>,
+    #[serde(default)]
+    compute_budget: ComputeBudgetSection,
+    #[serde(default)]
+    payer: PayerSection,
+    #[serde(default)]
+    publish: PublishSection,
+    #[serde(default)]
+    providers: Vec<ProviderSection>,
+    #[serde(default)]
+    feed_groups: Vec<FeedGroupSection>,
+}
+
+/// Compute budget instructions for the transactions sent to `cluster`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComputeBudgetSection {
+    unit_price: Option<UnitPriceSetting>,
+    max_unit_price: Option<u64>,
+    unit_limit_per_update: Option<u32>,
+}
+
+/// Micro-lamports per compute unit, or `"dynamic"`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum UnitPriceSetting {
+    MicroLamports(u64),
+    Mode(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PayerSection {
+    /// Base58 encoded keypair.
+    private_key: Option<String>,
+    /// Keypair file in the Solana CLI's JSON format, used when `private_key` is not set.
+    keypair_file: Option<String>,
+    #[serde(default)]
+    extra_keys: Vec<String>,
+    selection: Option<String>,
+    min_balance: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PublishSection {
+    deviation_bps: Option<u32>,
+    heartbeat_secs: Option<u64>,
+    /// Overrides keyed by feed name.
+    #[serde(default)]
+    feeds: HashMap<String, FeedPublishPolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeedPublishPolicy {
+    deviation_bps: u32,
+    #[serde(default)]
+    heartbeat_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProviderSection {
+    /// `pyth-lazer` or `stork`.
+    provider: String,
+    /// Taken from `--ws-urls` when left out.
+    #[serde(default)]
+    urls: Vec<String>,
+    /// Same format as a `--credential` value without the target.
+    credential: Option<String>,
+    channel: Option<String>,
+    delivery_format: Option<String>,
+    redundant: Option<bool>,
+    max_feeds_per_connection: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FeedGroupSection {
+    name: Option<String>,
+    provider: String,
+    feeds: Vec<String>,
+    /// Overrides the provider's channel for this group.
+    channel: Option<String>,
+}
+
+/// A provider and the feed groups that connect to it.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub provider: String,
+    pub urls: Vec<String>,
+    pub channel: Option<ChannelType>,
+    pub delivery_format: Option<DeliveryFormat>,
+    pub redundant: Option<bool>,
+    pub max_feeds_per_connection: Option<usize>,
+    /// Empty when the file has no feed groups for this provider.
+    pub feed_groups: Vec<FeedGroupConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedGroupConfig {
+    pub name: String,
+    pub feeds: Vec<String>,
+    pub channel: Option<ChannelType>,
+}
+
+const PROVIDERS: [&str; 2] = ["pyth-lazer", "stork"];
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let config: ConfigFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut seen = vec![];
+        for provider in &self.providers {
+            if !PROVIDERS.contains(&provider.provider.as_str()) {
+                return Err(format!(
+                    "Unknown provider '{}'. Accepted values: {}",
+                    provider.provider,
+                    PROVIDERS.join(", ")
+                )
+                .into());
+            }
+            if seen.contains(&&provider.provider) {
+                return Err(format!("Provider '{}' is listed twice", provider.provider).into());
+            }
+            seen.push(&provider.provider);
+        }
+        for group in &self.feed_groups {
+            if !seen.contains(&&group.provider) {
+                return Err(format!(
+                    "Feed group refers to provider '{}', which is not configured",
+                    group.provider
+                )
+                .into());
+            }
+        }
+        let mut names = vec![];
+        for provider in self.providers()? {
+            for group in provider.feed_groups {
+                if names.contains(&group.name) {
+                    return Err(format!("Feed group '{}' is listed twice", group.name).into());
+                }
+                names.push(group.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in the arguments that were not given on the command line.
+    pub fn apply_to(&self, args: &mut Args) -> Result<(), Box<dyn Error>> {
+        args.cluster = args.cluster.take().or(self.cluster.clone());
+        if args.send_urls.is_empty() {
+            args.send_urls = self.send_urls.clone();
+        }
+        args.cluster_ws_url = args.cluster_ws_url.take().or(self.cluster_ws_url.clone());
+        args.blockhash_refresh = match args.blockhash_refresh {
+            Some(mode) => Some(mode),
+            None => parse_value::<BlockhashRefreshMode>(&self.blockhash_refresh)?,
+        };
+
+        args.compute_unit_price =
+            args.compute_unit_price
+                .take()
+                .or(match &self.compute_budget.unit_price {
+                    Some(UnitPriceSetting::MicroLamports(price)) => Some(price.to_string()),
+                    Some(UnitPriceSetting::Mode(mode)) => Some(mode.clone()),
+                    None => None,
+                });
+        args.max_compute_unit_price = args
+            .max_compute_unit_price
+            .or(self.compute_budget.max_unit_price);
+        args.compute_unit_limit = args
+            .compute_unit_limit
+            .or(self.compute_budget.unit_limit_per_update);
+
+        let private_key = match (&self.payer.private_key, &self.payer.keypair_file) {
+            (Some(key), _) => Some(key.clone()),
+            (None, Some(path)) => Some(read_keypair_file(path)?.to_base58_string()),
+            (None, None) => None,
+        };
+        args.private_key = args.private_key.take().or(private_key);
+        if args.payer_keys.is_empty() {
+            args.payer_keys = self.payer.extra_keys.clone();
+        }
+        args.payer_selection = match args.payer_selection {
+            Some(selection) => Some(selection),
+            None => parse_value::<PayerSelection>(&self.payer.selection)?,
+        };
+        args.min_payer_balance = args.min_payer_balance.or(self.payer.min_balance);
+
+        args.publish_deviation_bps = args.publish_deviation_bps.or(self.publish.deviation_bps);
+        args.publish_heartbeat_secs = args.publish_heartbeat_secs.or(self.publish.heartbeat_secs);
+        if args.publish_policies.is_empty() {
+            args.publish_policies = self
+                .publish
+                .feeds
+                .iter()
+                .map(|(symbol, policy)| {
+                    format!(
+                        "{}={}:{}",
+                        symbol, policy.deviation_bps, policy.heartbeat_secs
+                    )
+                })
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// `<provider>=<credential>` entries for the providers that have a credential.
+    pub fn credentials(&self) -> Vec<String> {
+        self.providers
+            .iter()
+            .filter_map(|provider| {
+                let credential = provider.credential.as_ref()?;
+                Some(format!("{}={}", provider.provider, credential))
+            })
+            .collect()
+    }
+
+    /// The configured providers with their feed groups. Empty when the file lists no providers.
+    /// Unnamed groups are named after their provider, numbered when it has more than one.
+    pub fn providers(&self) -> Result<Vec<ProviderConfig>, Box<dyn Error>> {
+        self.providers
+            .iter()
+            .map(|provider| {
+                let groups: Vec<&FeedGroupSection> = self
+                    .feed_groups
+                    .iter()
+                    .filter(|group| group.provider == provider.provider)
+                    .collect();
+                let feed_groups = groups
+                    .iter()
+                    .enumerate()
+                    .map(|(i, group)| {
+                        let name = match &group.name {
+                            Some(name) => name.clone(),
+                            None if groups.len() == 1 => provider.provider.clone(),
+                            None => format!("{}-{}", provider.provider, i + 1),
+                        };
+                        Ok(FeedGroupConfig {
+                            name,
+                            feeds: group.feeds.clone(),
+                            channel: parse_value(&group.channel)?,
+                        })
+                    })
+                    .collect::<Result<_, Box<dyn Error>>>()?;
+                Ok(ProviderConfig {
+                    provider: provider.provider.clone(),
+                    urls: provider.urls.clone(),
+                    channel: parse_value(&provider.channel)?,
+                    delivery_format: parse_value(&provider.delivery_format)?,
+                    redundant: provider.redundant,
+                    max_feeds_per_connection: provider.max_feeds_per_connection,
+                    feed_groups,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Parses a value the same way its command line argument is parsed.
+fn parse_value<T: ValueEnum>(value: &Option<String>) -> Result<Option<T>, Box<dyn Error>> {
+    value
+        .as_deref()
+        .map(|value| T::from_str(value, true).map_err(|e| e.into()))
+        .transpose()
+}
+
+/// Reads a keypair file in the Solana CLI's format, a JSON array of the 64 keypair bytes.
+fn read_keypair_file(path: &str) -> Result<Keypair, Box<dyn Error>> {
+    let bytes: Vec<u8> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(Keypair::from_bytes(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{get_compute_budget, get_min_payer_balance};
+    use crate::compute_budget::UnitPrice;
+    use clap::Parser;
+
+    fn config(toml: &str) -> Result<ConfigFile, Box<dyn Error>> {
+        let config: ConfigFile = toml::from_str(toml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn args(cli: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("solana-pusher").chain(cli.iter().copied()))
+    }
+
+    fn group_names(config: &ConfigFile) -> Vec<String> {
+        config
+            .providers()
+            .unwrap()
+            .into_iter()
+            .flat_map(|provider| provider.feed_groups)
+            .map(|group| group.name)
+            .collect()
+    }
+
+    #[test]
+    fn rejects_unknown_provider() {
+        let err = config("[[providers]]\nprovider = \"pyth\"").unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown provider 'pyth'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_provider() {
+        let err = config(
+            r#"
+            [[providers]]
+            provider = "stork"
+            [[providers]]
+            provider = "stork"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("listed twice"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_group_of_unconfigured_provider() {
+        let err = config(
+            r#"
+            [[providers]]
+            provider = "stork"
+            [[feed_groups]]
+            provider = "pyth-lazer"
+            feeds = ["1"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("not configured"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(config("[payer]\nkey = \"abc\"").is_err());
+    }
+
+    #[test]
+    fn names_unnamed_groups_after_their_provider() {
+        let config = config(
+            r#"
+            [[providers]]
+            provider = "pyth-lazer"
+            [[providers]]
+            provider = "stork"
+            [[feed_groups]]
+            provider = "pyth-lazer"
+            feeds = ["1"]
+            [[feed_groups]]
+            provider = "pyth-lazer"
+            feeds = ["2"]
+            channel = "real_time"
+            [[feed_groups]]
+            provider = "stork"
+            feeds = ["BTCUSD"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            group_names(&config),
+            ["pyth-lazer-1", "pyth-lazer-2", "stork"]
+        );
+        let lazer = &config.providers().unwrap()[0];
+        assert!(lazer.feed_groups[0].channel.is_none());
+        assert!(matches!(
+            lazer.feed_groups[1].channel,
+            Some(ChannelType::RealTime)
+        ));
+    }
+
+    #[test]
+    fn rejects_duplicate_group_names() {
+        let err = config(
+            r#"
+            [[providers]]
+            provider = "stork"
+            [[feed_groups]]
+            name = "majors"
+            provider = "stork"
+            feeds = ["BTCUSD"]
+            [[feed_groups]]
+            name = "majors"
+            provider = "stork"
+            feeds = ["ETHUSD"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("'majors'"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_group_named_like_a_generated_name() {
+        let err = config(
+            r#"
+            [[providers]]
+            provider = "pyth-lazer"
+            [[feed_groups]]
+            provider = "pyth-lazer"
+            feeds = ["1"]
+            [[feed_groups]]
+            name = "pyth-lazer-1"
+            provider = "pyth-lazer"
+            feeds = ["2"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("'pyth-lazer-1'"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_invalid_channel() {
+        let err = config(
+            r#"
+            [[providers]]
+            provider = "pyth-lazer"
+            channel = "every_second"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("every_second"), "{}", err);
+    }
+
+    #[test]
+    fn command_line_wins_over_file() {
+        let config = config(
+            r#"
+            cluster = "https://file.example.com"
+            send_urls = ["https://file-send.example.com"]
+            blockhash_refresh = "subscribe"
+            [payer]
+            selection = "feed-shard"
+            min_balance = 5
+            [publish]
+            deviation_bps = 5
+            [publish.feeds]
+            "BTCUSD" = { deviation_bps = 2, heartbeat_secs = 10 }
+            "#,
+        )
+        .unwrap();
+
+        let mut from_file = args(&[]);
+        config.apply_to(&mut from_file).unwrap();
+        assert_eq!(
+            from_file.cluster.as_deref(),
+            Some("https://file.example.com")
+        );
+        assert_eq!(from_file.send_urls, ["https://file-send.example.com"]);
+        assert_eq!(
+            from_file.blockhash_refresh,
+            Some(BlockhashRefreshMode::Subscribe)
+        );
+        assert_eq!(from_file.payer_selection, Some(PayerSelection::FeedShard));
+        assert_eq!(from_file.publish_deviation_bps, Some(5));
+        assert_eq!(from_file.publish_policies, ["BTCUSD=2:10"]);
+
+        let mut from_cli = args(&[
+            "--cluster",
+            "https://cli.example.com",
+            "--send-urls",
+            "https://cli-send.example.com",
+            "--blockhash-refresh",
+            "poll",
+            "--payer-selection",
+            "round-robin",
+            "--publish-deviation-bps",
+            "1",
+            "--publish-policy",
+            "ETHUSD=3:0",
+        ]);
+        config.apply_to(&mut from_cli).unwrap();
+        assert_eq!(from_cli.cluster.as_deref(), Some("https://cli.example.com"));
+        assert_eq!(from_cli.send_urls, ["https://cli-send.example.com"]);
+        assert_eq!(from_cli.blockhash_refresh, Some(BlockhashRefreshMode::Poll));
+        assert_eq!(from_cli.payer_selection, Some(PayerSelection::RoundRobin));
+        assert_eq!(from_cli.publish_deviation_bps, Some(1));
+        assert_eq!(from_cli.publish_policies, ["ETHUSD=3:0"]);
+    }
+
+    #[test]
+    fn environment_wins_over_file() {
+        let config = config("[payer]\nmin_balance = 5").unwrap();
+        let mut args = args(&[]);
+        config.apply_to(&mut args).unwrap();
+        assert_eq!(args.min_payer_balance, Some(5));
+        std::env::set_var("ORACLE_MIN_PAYER_BALANCE", "7");
+        let min_balance = get_min_payer_balance(args.min_payer_balance);
+        std::env::remove_var("ORACLE_MIN_PAYER_BALANCE");
+        assert_eq!(min_balance, 7);
+    }
+
+    #[test]
+    fn private_key_wins_over_keypair_file() {
+        let file_key = Keypair::new();
+        let path = std::env::temp_dir().join(format!(
+            "solana-pusher-config-test-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            serde_json::to_string(&file_key.to_bytes().to_vec()).unwrap(),
+        )
+        .unwrap();
+        let keypair_file = format!("[payer]\nkeypair_file = {:?}", path.to_str().unwrap());
+
+        let mut args_from_file = args(&[]);
+        config(&keypair_file)
+            .unwrap()
+            .apply_to(&mut args_from_file)
+            .unwrap();
+
+        let inline_key = Keypair::new().to_base58_string();
+        let mut args_inline = args(&[]);
+        config(&format!("{}\nprivate_key = {:?}", keypair_file, inline_key))
+            .unwrap()
+            .apply_to(&mut args_inline)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            args_from_file.private_key,
+            Some(file_key.to_base58_string())
+        );
+        assert_eq!(args_inline.private_key, Some(inline_key));
+    }
+
+    #[test]
+    fn unit_price_is_numeric_or_dynamic() {
+        let apply = |toml: &str| {
+            let mut args = args(&[]);
+            config(toml).unwrap().apply_to(&mut args).unwrap();
+            get_compute_budget(
+                args.compute_unit_price,
+                args.max_compute_unit_price,
+                args.compute_unit_limit,
+            )
+        };
+
+        let fixed = apply("[compute_budget]\nunit_price = 1000\nunit_limit_per_update = 9000");
+        assert_eq!(fixed.unit_price, Some(UnitPrice::Fixed(1000)));
+        assert_eq!(fixed.unit_limit_per_update, Some(9000));
+
+        let dynamic = apply("[compute_budget]\nunit_price = \"dynamic\"\nmax_unit_price = 500");
+        assert_eq!(dynamic.unit_price, Some(UnitPrice::Dynamic { max: 500 }));
+
+        assert!(config("[compute_budget]\nunit_price = -1").is_err());
+    }
+}
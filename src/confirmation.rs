@@ -0,0 +1,268 @@
+use crate::blockhash_cache::BlockhashCache;
+use crate::publish_policy::PublishFilter;
+use crate::retry::{is_retryable_transaction_error, Retry, RetryQueue};
+use crate::types::UpdateData;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::TransactionError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// `getSignatureStatuses` accepts at most this many signatures per call.
+const MAX_SIGNATURES_PER_POLL: usize = 256;
+const POLL_INTERVAL: Duration = Duration::from_millis(400);
+/// Blockhashes stay valid for 150 blocks, about 60s at 400ms slots, so a transaction still
+/// unseen this long after sending can no longer land. Transactions normally expire once the
+/// block height passes their blockhash's last valid one; this covers the block height not
+/// advancing because the RPC can't be reached.
+const EXPIRE_AFTER: Duration = Duration::from_secs(90);
+
+/// How a sent transaction ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Landed,
+    Failed(TransactionError),
+    Expired,
+}
+
+struct PendingTransaction {
+    provider: String,
+    updates: Vec<UpdateData>,
+    attempt: u32,
+    last_valid_block_height: u64,
+    sent_at: Instant,
+    /// Seen processed but not confirmed yet. It may still confirm after its blockhash expired,
+    /// so it is only given up after `EXPIRE_AFTER`.
+    processed: bool,
+}
+
+/// Delivery counts and latency of one feed since startup.
+#[derive(Debug, Default, Clone)]
+pub struct FeedStats {
+    pub sent: u64,
+    pub landed: u64,
+    pub failed: u64,
+    pub expired: u64,
+    /// Sum of the time from the provider's price timestamp until the update was seen confirmed.
+    total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl FeedStats {
+    pub fn landing_rate(&self) -> f64 {
+        let settled = self.landed + self.failed + self.expired;
+        if settled == 0 {
+            return 0.0;
+        }
+        self.landed as f64 / settled as f64
+    }
+
+    pub fn average_latency(&self) -> Duration {
+        if self.landed == 0 {
+            return Duration::ZERO;
+        }
+        self.total_latency / self.landed as u32
+    }
+}
+
+/// Follows every sent transaction until it lands, fails on-chain or expires, and keeps per-feed
+/// landing rates and end-to-end latency. Expired transactions and transient failures are handed
+/// to `retries`, while updates that failed for good are forgotten by `publish_filter`.
+pub struct ConfirmationTracker {
+    rpc_client: Arc<RpcClient>,
+    blockhash_cache: BlockhashCache,
+    retries: RetryQueue,
+    publish_filter: Arc<PublishFilter>,
+    pending: Mutex<HashMap<Signature, PendingTransaction>>,
+    stats: Mutex<HashMap<String, FeedStats>>,
+}
+
+impl ConfirmationTracker {
+    /// Creates the tracker and starts polling signature statuses in the background.
+    pub fn spawn(
+        rpc_client: Arc<RpcClient>,
+        blockhash_cache: BlockhashCache,
+        retries: RetryQueue,
+        publish_filter: Arc<PublishFilter>,
+    ) -> Arc<Self> {
+        let tracker = Arc::new(ConfirmationTracker {
+            rpc_client,
+            blockhash_cache,
+            retries,
+            publish_filter,
+            pending: Mutex::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+        });
+
+        let poller = tracker.clone();
+        tokio::spawn(async move {
+            loop {
+                time::sleep(POLL_INTERVAL).await;
+                poller.poll().await;
+                poller.expire();
+            }
+        });
+        tracker
+    }
+
+    pub fn track(
+        &self,
+        signature: Signature,
+        provider: String,
+        updates: Vec<UpdateData>,
+        attempt: u32,
+        last_valid_block_height: u64,
+    ) {
+        let mut stats = self.stats.lock().unwrap();
+        for update in &updates {
+            stats.entry(update.symbol.clone()).or_default().sent += 1;
+        }
+        drop(stats);
+
+        self.pending.lock().unwrap().insert(
+            signature,
+            PendingTransaction {
+                provider,
+                updates,
+                attempt,
+                last_valid_block_height,
+                sent_at: Instant::now(),
+                processed: false,
+            },
+        );
+    }
+
+    async fn poll(&self) {
+        let signatures: Vec<Signature> = self.pending.lock().unwrap().keys().copied().collect();
+
+        for batch in signatures.chunks(MAX_SIGNATURES_PER_POLL) {
+            let statuses = match self.rpc_client.get_signature_statuses(batch).await {
+                Ok(response) => response.value,
+                Err(e) => {
+                    // expiry runs after every poll, so transactions still expire meanwhile
+                    warn!(error = ?e, "Failed to fetch signature statuses");
+                    return;
+                }
+            };
+
+            for (signature, status) in batch.iter().zip(statuses) {
+                let Some(status) = status else {
+                    continue;
+                };
+                if let Some(err) = status.err.clone() {
+                    self.settle(signature, Outcome::Failed(err));
+                } else if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    self.settle(signature, Outcome::Landed);
+                } else if let Some(pending) = self.pending.lock().unwrap().get_mut(signature) {
+                    // processed, it will either confirm or show an error later
+                    pending.processed = true;
+                }
+            }
+        }
+    }
+
+    /// Settles the transactions that can no longer land as expired: the block height passed
+    /// the last valid one of their blockhash before they were processed, or `EXPIRE_AFTER` went
+    /// by since they were sent.
+    /// Runs whether statuses could be fetched or not, so an RPC outage doesn't keep
+    /// transactions pending forever.
+    fn expire(&self) {
+        let block_height = self.blockhash_cache.synced_block_height();
+        let expired: Vec<Signature> = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, pending)| {
+                (!pending.processed && block_height > pending.last_valid_block_height)
+                    || pending.sent_at.elapsed() >= EXPIRE_AFTER
+            })
+            .map(|(signature, _)| *signature)
+            .collect();
+        for signature in expired {
+            self.settle(&signature, Outcome::Expired);
+        }
+    }
+
+    fn settle(&self, signature: &Signature, outcome: Outcome) {
+        let Some(pending) = self.pending.lock().unwrap().remove(signature) else {
+            return;
+        };
+
+        match &outcome {
+            Outcome::Landed => debug!(signature = %signature, "Transaction landed"),
+            Outcome::Failed(err) => {
+                warn!(signature = %signature, error = %err, "Transaction failed on-chain")
+            }
+            Outcome::Expired => warn!(signature = %signature, "Transaction expired"),
+        }
+
+        let now_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let mut stats = self.stats.lock().unwrap();
+        for update in &pending.updates {
+            let feed = stats.entry(update.symbol.clone()).or_default();
+            match outcome {
+                Outcome::Landed => {
+                    let latency = Duration::from_nanos(
+                        now_ns.saturating_sub(update.temporal_numeric_value.timestamp_ns),
+                    );
+                    feed.landed += 1;
+                    feed.total_latency += latency;
+                    feed.max_latency = feed.max_latency.max(latency);
+                }
+                Outcome::Failed(_) => feed.failed += 1,
+                Outcome::Expired => feed.expired += 1,
+            }
+        }
+        drop(stats);
+
+        let reason = match outcome {
+            Outcome::Landed => return,
+            Outcome::Failed(err) if !is_retryable_transaction_error(&err) => {
+                self.publish_filter
+                    .forget(&pending.provider, &pending.updates);
+                return;
+            }
+            Outcome::Failed(err) => err.to_string(),
+            Outcome::Expired => "expired".to_string(),
+        };
+        self.retries.push(Retry {
+            provider: pending.provider,
+            updates: pending.updates,
+            attempt: pending.attempt,
+            reason,
+            sent: true,
+        });
+    }
+
+    pub fn feed_stats(&self) -> HashMap<String, FeedStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn log_stats(&self) {
+        let pending = self.pending.lock().unwrap().len();
+        let mut stats: Vec<_> = self.feed_stats().into_iter().collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        for (feed, stats) in stats {
+            info!(
+                feed = %feed,
+                sent = stats.sent,
+                landed = stats.landed,
+                failed = stats.failed,
+                expired = stats.expired,
+                landing_rate = format!("{:.3}", stats.landing_rate()),
+                avg_latency_ms = stats.average_latency().as_millis() as u64,
+                max_latency_ms = stats.max_latency.as_millis() as u64,
+                "Feed delivery stats"
+            );
+        }
+        info!(pending, "Transactions awaiting confirmation");
+    }
+}
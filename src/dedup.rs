@@ -0,0 +1,34 @@
+use crate::types::UpdateData;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Keeps track of the newest timestamp seen for each feed so that the same update delivered
+/// by several WebSocket connections is only pushed on-chain once.
+#[derive(Default)]
+pub struct UpdateDeduplicator {
+    latest_timestamps: Mutex<HashMap<String, u64>>,
+}
+
+impl UpdateDeduplicator {
+    /// Returns the updates that are strictly newer than the last one seen for their feed.
+    pub fn retain_fresh(&self, updates: Vec<UpdateData>) -> Vec<UpdateData> {
+        let mut latest_timestamps = self.latest_timestamps.lock().unwrap();
+        updates
+            .into_iter()
+            .filter(|update| {
+                let timestamp = update.temporal_numeric_value.timestamp_ns;
+                match latest_timestamps.get_mut(&update.symbol) {
+                    Some(latest) if *latest >= timestamp => false,
+                    Some(latest) => {
+                        *latest = timestamp;
+                        true
+                    }
+                    None => {
+                        latest_timestamps.insert(update.symbol.clone(), timestamp);
+                        true
+                    }
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,91 @@
+use crate::instructions::{UpdatePriceFeed, ID};
+use borsh::BorshDeserialize;
+use serde_json::{json, Value};
+use solana_sdk::compute_budget::{self, ComputeBudgetInstruction};
+use solana_sdk::transaction::Transaction;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Where dry-run transactions are written instead of being sent, one JSON object per line.
+pub struct DryRunOutput {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl DryRunOutput {
+    /// Writes to the file at `path`, or to stdout when there is none.
+    pub fn open(path: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let out: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(DryRunOutput {
+            out: Mutex::new(out),
+        })
+    }
+
+    pub fn write(&self, provider: &str, attempt: u32, tx: &Transaction) -> io::Result<()> {
+        let line = serde_json::to_string(&describe(provider, attempt, tx))?;
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{}", line)?;
+        out.flush()
+    }
+}
+
+fn describe(provider: &str, attempt: u32, tx: &Transaction) -> Value {
+    let message = &tx.message;
+    let instructions: Vec<Value> = message
+        .instructions
+        .iter()
+        .map(|ix| {
+            let program_id = message.account_keys[ix.program_id_index as usize];
+            let accounts: Vec<String> = ix
+                .accounts
+                .iter()
+                .map(|&index| message.account_keys[index as usize].to_string())
+                .collect();
+            let decoded = if program_id == ID {
+                decode_update(&ix.data, &accounts)
+            } else if program_id == compute_budget::id() {
+                borsh::from_slice::<ComputeBudgetInstruction>(&ix.data)
+                    .ok()
+                    .and_then(|ix| serde_json::to_value(ix).ok())
+            } else {
+                None
+            };
+            json!({
+                "program_id": program_id.to_string(),
+                "accounts": accounts,
+                "decoded": decoded,
+            })
+        })
+        .collect();
+
+    json!({
+        "provider": provider,
+        "attempt": attempt,
+        "signature": tx.signatures.first().map(|signature| signature.to_string()),
+        "payer": message.account_keys.first().map(|payer| payer.to_string()),
+        "recent_blockhash": message.recent_blockhash.to_string(),
+        // compact-u16 signature count, the signatures, then the message
+        "size": 1 + 64 * tx.signatures.len() + message.serialize().len(),
+        "instructions": instructions,
+    })
+}
+
+/// Decodes an `update_price_feed` instruction, whose data is the Anchor discriminator followed
+/// by the Borsh encoded arguments. The second account is the price feed PDA.
+fn decode_update(data: &[u8], accounts: &[String]) -> Option<Value> {
+    let args = UpdatePriceFeed::try_from_slice(data.get(8..)?).ok()?;
+    let update = args.update_data;
+    Some(json!({
+        "instruction": "update_price_feed",
+        "provider": args.provider,
+        "symbol": update.symbol,
+        "price_feed": accounts.get(1),
+        "feed_id": hex::encode(update.id),
+        "quantized_value": update.temporal_numeric_value.quantized_value.to_string(),
+        "timestamp_ns": update.temporal_numeric_value.timestamp_ns,
+    }))
+}
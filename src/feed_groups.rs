@@ -0,0 +1,114 @@
+use crate::subscriptions::{FeedChange, FeedSubscriptions};
+use crate::types::ChainPusher;
+use std::sync::{Arc, Mutex};
+
+/// The feeds of one feed group, spread over as many connections as the provider's limit on
+/// feeds per connection requires. Each connection keeps its own subscriptions, so feeds added
+/// at runtime go to the connection with the most room.
+pub struct FeedGroup {
+    pub name: String,
+    chain_pusher: Arc<dyn ChainPusher>,
+    connections: Vec<Arc<FeedSubscriptions>>,
+    max_feeds_per_connection: Option<usize>,
+    /// Held from checking where a feed goes until it is there, so concurrent subscribes can't
+    /// add the same feed twice or fill a connection past the limit.
+    assignment: Mutex<()>,
+}
+
+impl FeedGroup {
+    /// Splits `feeds` into connections of at most `max_feeds_per_connection` feeds. A group
+    /// always has at least one connection, so feeds can be added to an empty one.
+    pub fn new(
+        name: String,
+        chain_pusher: Arc<dyn ChainPusher>,
+        feeds: Vec<String>,
+        max_feeds_per_connection: Option<usize>,
+    ) -> Self {
+        let mut unique: Vec<String> = vec![];
+        for feed in feeds {
+            if !unique.contains(&feed) {
+                unique.push(feed);
+            }
+        }
+        let chunk_size = max_feeds_per_connection.unwrap_or(unique.len()).max(1);
+        let mut connections: Vec<Arc<FeedSubscriptions>> = unique
+            .chunks(chunk_size)
+            .map(|feeds| Arc::new(FeedSubscriptions::new(feeds.to_vec())))
+            .collect();
+        if connections.is_empty() {
+            connections.push(Arc::new(FeedSubscriptions::new(vec![])));
+        }
+        Self {
+            name,
+            chain_pusher,
+            connections,
+            max_feeds_per_connection,
+            assignment: Mutex::new(()),
+        }
+    }
+
+    /// The subscriptions of each connection the group runs.
+    pub fn connections(&self) -> &[Arc<FeedSubscriptions>] {
+        &self.connections
+    }
+
+    pub fn feeds(&self) -> Vec<String> {
+        self.connections
+            .iter()
+            .flat_map(|connection| connection.feeds())
+            .collect()
+    }
+
+    /// Adds `feed` to the connection with the fewest feeds. Returns `Ok(false)` when the group
+    /// already has it, and an error when the provider doesn't know the feed or every connection
+    /// is at the limit.
+    pub async fn subscribe(&self, feed: &str) -> Result<bool, String> {
+        if self.feeds().iter().any(|existing| existing == feed) {
+            return Ok(false);
+        }
+        let unknown = self
+            .chain_pusher
+            .unknown_feeds(&[feed.to_string()])
+            .await
+            .map_err(|e| format!("failed to look up feed '{}': {}", feed, e))?;
+        if !unknown.is_empty() {
+            return Err(format!(
+                "unknown {} feed '{}'",
+                self.chain_pusher.provider_name(),
+                feed
+            ));
+        }
+
+        let _assignment = self.assignment.lock().unwrap();
+        // another subscribe may have added it during the lookup
+        if self.feeds().iter().any(|existing| existing == feed) {
+            return Ok(false);
+        }
+        let connection = self
+            .connections
+            .iter()
+            .min_by_key(|connection| connection.feeds().len())
+            .expect("a feed group has at least one connection");
+        if self
+            .max_feeds_per_connection
+            .is_some_and(|max| connection.feeds().len() >= max)
+        {
+            return Err(format!(
+                "every connection of feed group '{}' is at the limit of {} feeds",
+                self.name,
+                self.max_feeds_per_connection.unwrap_or_default()
+            ));
+        }
+        Ok(connection.apply(FeedChange::Subscribe(feed.to_string())))
+    }
+
+    /// Removes `feed` from whichever connection has it. Returns `false` when none did.
+    pub fn unsubscribe(&self, feed: &str) -> bool {
+        let _assignment = self.assignment.lock().unwrap();
+        let mut changed = false;
+        for connection in &self.connections {
+            changed |= connection.apply(FeedChange::Unsubscribe(feed.to_string()));
+        }
+        changed
+    }
+}
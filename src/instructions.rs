@@ -4,22 +4,18 @@ use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::pubkey::Pubkey;
 use solana_sdk::pubkey;
 
-const ID: Pubkey = pubkey!("PriCems5tHihc6UDXDjzjeawomAwBduWMGAi8ZUjppd");
+pub const ID: Pubkey = pubkey!("PriCems5tHihc6UDXDjzjeawomAwBduWMGAi8ZUjppd");
 
-pub fn update_price_feed(
-    payer: &Pubkey,
-    provider: &String,
-    update_data: &UpdateData,
-) -> Instruction {
-    let price_feed = Pubkey::find_program_address(
-        &[
-            b"price_feed",
-            provider.as_bytes(),
-            update_data.symbol.as_bytes(),
-        ],
+pub fn price_feed_address(provider: &str, symbol: &str) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"price_feed", provider.as_bytes(), symbol.as_bytes()],
         &ID,
     )
-    .0;
+    .0
+}
+
+pub fn update_price_feed(payer: &Pubkey, provider: &str, update_data: &UpdateData) -> Instruction {
+    let price_feed = price_feed_address(provider, &update_data.symbol);
     Instruction {
         program_id: ID,
         accounts: vec![
@@ -27,7 +23,7 @@ pub fn update_price_feed(
             AccountMeta::new(price_feed, false),
         ],
         data: UpdatePriceFeed {
-            provider: provider.clone(),
+            provider: provider.to_string(),
             update_data: update_data.clone(),
         }
         .data(),
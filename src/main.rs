@@ -1,7 +1,29 @@
+mod admin;
 mod args;
+mod auth;
 mod blockhash_cache;
+mod compute_budget;
+mod config;
+mod confirmation;
+mod dedup;
+mod dry_run;
+mod feed_groups;
 mod instructions;
+mod payers;
+mod pipeline;
+mod preflight;
+mod proxy;
+mod publish_policy;
+mod reconnect;
+mod retry;
+mod rpc_endpoints;
+mod sender;
+mod shutdown;
+mod subscriptions;
+mod tls;
 mod types;
+mod watchdog;
+mod websocket;
 
 mod stork {
     pub mod chain_pusher;
@@ -12,160 +34,342 @@ mod pyth_lazer {
     pub mod price_parser;
 }
 
-use bytes::BytesMut;
 use clap::Parser;
-use native_tls::TlsConnector as NativeTlsConnector;
-use ratchet_rs::{
-    deflate::DeflateExtProvider, HeaderValue, Message, PayloadType, TryIntoRequest, UpgradedClient,
-    WebSocketClientBuilder, WebSocketStream,
-};
-use solana_sdk::signature::{Keypair, Signer};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::signature::Keypair;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio::time::{self, Duration};
-use tokio_native_tls::TlsConnector;
-use tracing::{debug, error, info, warn};
-use url::Url;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
+use crate::admin::run_admin_socket;
 use crate::args::{
-    get_auth_header, get_channel, get_price_feeds, get_private_key, get_solana_cluster,
+    get_admin_socket, get_auth_header, get_blockhash_refresh, get_channel, get_compute_budget,
+    get_config_path, get_credentials, get_delivery_format, get_dry_run, get_dry_run_output,
+    get_feed_stale_after, get_feed_stale_reconnect, get_max_blockhash_age,
+    get_max_feeds_per_connection, get_min_payer_balance, get_payer_keys, get_payer_selection,
+    get_pipeline_config, get_preflight, get_price_feeds, get_private_key, get_proxy,
+    get_publish_policy, get_reconnect_policy, get_redundant, get_retry_policy, get_rpc_urls,
+    get_shutdown_timeout, get_solana_cluster, get_tls_backend, get_tls_ca_file, get_tls_pins,
     get_ws_urls, Args,
 };
+use crate::auth::Credentials;
+use crate::config::{ConfigFile, FeedGroupConfig, ProviderConfig};
+use crate::dry_run::DryRunOutput;
+use crate::feed_groups::FeedGroup;
+use crate::payers::{parse_keypair, PayerPool};
+use crate::proxy::ProxyConfig;
 use crate::pyth_lazer::chain_pusher::PythChainPusher;
+use crate::reconnect::EndpointHealth;
+use crate::rpc_endpoints::redact_url;
+use crate::sender::{SenderConfig, TransactionSender};
+use crate::shutdown::shutdown_signal;
 use crate::stork::chain_pusher::StorkChainPusher;
+use crate::tls::{check_pinned_urls, parse_pins, TlsConfig};
 use crate::types::ChainPusher;
+use crate::websocket::{
+    run_failover_websocket_clients, run_redundant_websocket_clients, ConnectionSettings,
+};
 
 #[tokio::main]
 async fn main() {
+    dotenvy::dotenv().ok();
+    let mut args = Args::parse();
+    // the file only fills in what the command line left unset, environment variables still win
+    let config_path = get_config_path(args.config.clone());
+    let config = match &config_path {
+        Some(path) => ConfigFile::load(path)
+            .unwrap_or_else(|e| panic!("Invalid config file '{}': {}", path, e)),
+        None => ConfigFile::default(),
+    };
+    config
+        .apply_to(&mut args)
+        .unwrap_or_else(|e| panic!("Invalid config file: {}", e));
+    let dry_run = get_dry_run(args.dry_run);
+    let dry_run_output = get_dry_run_output(args.dry_run_output);
+    // keep stdout for the transactions when a dry run writes them there
+    let log_writer = if dry_run && dry_run_output.is_none() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(log_writer)
         .init();
-    dotenvy::dotenv().ok();
-    let args = Args::parse();
     let private_key = get_private_key(args.private_key);
-    let auth_header = get_auth_header(args.auth_header);
-    let ws_urls = get_ws_urls(args.ws_url, args.ws_urls);
-    let cluster_url = get_solana_cluster(args.cluster);
+    // file credentials come first so the ones from the environment or command line replace them
+    let credentials = Credentials::parse(
+        &[config.credentials(), get_credentials(args.credentials)].concat(),
+        get_auth_header(args.auth_header),
+    )
+    .expect("Invalid credential");
+    if credentials.is_empty() {
+        panic!("ORACLE_AUTH_HEADER, ORACLE_CREDENTIALS or their command line arguments must be provided");
+    }
+    let cluster_url = get_solana_cluster(args.cluster, config_path.is_some());
+    let rpc_urls = get_rpc_urls(&cluster_url, args.send_urls);
+    info!(
+        rpc_urls = ?rpc_urls.iter().map(|url| redact_url(url)).collect::<Vec<_>>(),
+        "RPC endpoints configured"
+    );
+    // URLs are grouped by provider so each group gets the pusher that speaks its protocol
+    let (stork_urls, lazer_urls): (Vec<String>, Vec<String>) =
+        get_ws_urls(args.ws_url, args.ws_urls)
+            .into_iter()
+            .partition(|url| url.contains("stork"));
+    let mut providers = config
+        .providers()
+        .unwrap_or_else(|e| panic!("Invalid config file: {}", e));
+    if providers.is_empty() {
+        for (provider, urls) in [("stork", &stork_urls), ("pyth-lazer", &lazer_urls)] {
+            if !urls.is_empty() {
+                providers.push(ProviderConfig {
+                    provider: provider.to_string(),
+                    urls: urls.clone(),
+                    channel: None,
+                    delivery_format: None,
+                    redundant: None,
+                    max_feeds_per_connection: None,
+                    feed_groups: vec![],
+                });
+            }
+        }
+    }
     let price_feeds = get_price_feeds(args.price_feeds);
-    let channel = get_channel(args.channel);
-
-    let payer = Keypair::from_base58_string(&private_key);
-    info!(wallet_pubkey = ?payer.pubkey(), "Identity initialized");
+    let providers: Vec<_> = providers
+        .into_iter()
+        .map(|mut provider| {
+            if provider.urls.is_empty() {
+                provider.urls = if provider.provider == "stork" {
+                    stork_urls.clone()
+                } else {
+                    lazer_urls.clone()
+                };
+            }
+            if provider.urls.is_empty() {
+                panic!("No WebSocket URLs for provider '{}'", provider.provider);
+            }
+            // providers without feed groups subscribe to the --price-feeds list
+            let groups = if provider.feed_groups.is_empty() {
+                vec![FeedGroupConfig {
+                    name: provider.provider.clone(),
+                    feeds: price_feeds.clone(),
+                    channel: None,
+                }]
+            } else {
+                provider.feed_groups.clone()
+            };
+            (provider, groups)
+        })
+        .collect();
+    let reconnect_policy = get_reconnect_policy(
+        args.reconnect_initial_delay_ms,
+        args.reconnect_max_delay_ms,
+        args.reconnect_multiplier,
+        args.reconnect_jitter,
+    );
+    let endpoint_health = Arc::new(EndpointHealth::default());
+    let retry_policy = get_retry_policy(args.max_send_attempts, args.retry_delay_ms);
+    let compute_budget = get_compute_budget(
+        args.compute_unit_price,
+        args.max_compute_unit_price,
+        args.compute_unit_limit,
+    );
+    info!(compute_budget = ?compute_budget, "Compute budget configured");
+    let pipeline_config = get_pipeline_config(args.send_queue_capacity, args.max_concurrent_sends);
+    let publish_policy = get_publish_policy(
+        args.publish_deviation_bps,
+        args.publish_heartbeat_secs,
+        args.publish_policies,
+    );
+    info!(publish_policy = ?publish_policy, "Publish policy configured");
+    let preflight = get_preflight(args.preflight, args.preflight_sample_every);
+    let blockhash_refresh =
+        get_blockhash_refresh(args.blockhash_refresh, args.cluster_ws_url, &cluster_url);
+    info!(blockhash_refresh = ?blockhash_refresh, "Blockhash refresh configured");
+    info!(preflight = ?preflight, "Preflight checks configured");
+    let shutdown_timeout = get_shutdown_timeout(args.shutdown_timeout_secs);
+    let feed_stale_after = get_feed_stale_after(args.feed_stale_secs);
+    let feed_stale_reconnect = get_feed_stale_reconnect(args.feed_stale_reconnect);
+    let proxy =
+        get_proxy(args.proxy).map(|proxy| ProxyConfig::parse(&proxy).expect("Invalid proxy URL"));
+    if let Some(proxy) = &proxy {
+        info!(proxy = %proxy, "Provider connections go through a proxy");
+    }
 
-    let tls_connector = TlsConnector::from(NativeTlsConnector::new().expect("Failed to create TLS connector"));
+    let mut payers = vec![Keypair::from_base58_string(&private_key)];
+    for key in get_payer_keys(args.payer_keys) {
+        payers.push(parse_keypair(&key).expect("Invalid payer key"));
+    }
+    let payer_selection = get_payer_selection(args.payer_selection);
+    let payers = Arc::new(PayerPool::new(payers, payer_selection));
+    info!(payers = ?payers.pubkeys(), selection = %payer_selection, "Identity initialized");
+    payers.clone().spawn_balance_monitor(
+        Arc::new(RpcClient::new(cluster_url.clone())),
+        get_min_payer_balance(args.min_payer_balance),
+    );
 
-    let chain_pusher: Arc<dyn ChainPusher> = if ws_urls.iter().any(|url| url.contains("stork")) {
-        Arc::new(StorkChainPusher::new(&cluster_url, payer).await)
-    } else {
-        Arc::new(PythChainPusher::new(&cluster_url, payer).await)
-    };
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown requested, closing WebSocket connections");
+        let _ = shutdown_tx.send(true);
+    });
 
-    loop {
-        let mut last_error = None;
+    let tls_backend = get_tls_backend(args.tls_backend);
+    let tls_pins = parse_pins(&get_tls_pins(args.tls_pins)).expect("Invalid TLS pin");
+    info!(backend = %tls_backend, pinned_hosts = ?tls_pins.keys().collect::<Vec<_>>(), "TLS configured");
+    let provider_urls: Vec<String> = providers
+        .iter()
+        .flat_map(|(provider, _)| provider.urls.clone())
+        .collect();
+    check_pinned_urls(&tls_pins, &provider_urls).expect("Invalid TLS pin");
+    let tls = TlsConfig::new(
+        tls_backend,
+        get_tls_ca_file(args.tls_ca_file).as_deref(),
+        tls_pins,
+    )
+    .expect("Failed to create TLS connector");
+    let credentials = Arc::new(credentials);
 
-        for ws_url in &ws_urls {
-            match run_websocket_client(
-                &chain_pusher,
-                ws_url,
-                &auth_header,
-                &price_feeds,
-                &channel,
-                tls_connector.clone(),
-            )
-            .await
-            {
-                Ok(_) => break,
-                Err(e) => {
-                    error!(error = ?e, url = ws_url, "WebSocket connection failed, trying next URL");
-                    last_error = Some(e);
-                }
+    let dry_run = dry_run.then(|| {
+        info!(
+            output = dry_run_output.as_deref().unwrap_or("stdout"),
+            "Dry run, transactions are not sent"
+        );
+        DryRunOutput::open(dry_run_output.as_deref()).expect("Failed to open dry-run output")
+    });
+    let sender = TransactionSender::new(
+        &rpc_urls,
+        payers.clone(),
+        SenderConfig {
+            retry_policy,
+            pipeline: pipeline_config,
+            compute_budget,
+            publish_policy,
+            preflight,
+            blockhash_refresh,
+            max_blockhash_age: get_max_blockhash_age(args.max_blockhash_age_secs),
+        },
+        dry_run,
+    )
+    .await;
+    // every feed group runs its own connections, all sending through the same sender, RPC
+    // endpoints and blockhash cache
+    let mut clients = JoinSet::new();
+    let mut feed_groups = vec![];
+    for (provider, groups) in providers {
+        let chain_pusher: Arc<dyn ChainPusher> = if provider.provider == "stork" {
+            Arc::new(StorkChainPusher::new(sender.clone()))
+        } else {
+            let mut pusher = PythChainPusher::new(sender.clone());
+            if let Some(proxy) = &proxy {
+                pusher = pusher.with_http_client(
+                    proxy
+                        .http_client()
+                        .expect("Failed to create proxied HTTP client"),
+                );
             }
+            Arc::new(pusher)
+        };
+        let publish_overrides = sender.publish_overrides();
+        if !publish_overrides.is_empty() {
+            let feed_names = chain_pusher
+                .feed_names(&publish_overrides)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to look up feeds of publish policies: {}", e));
+            sender.name_feeds(feed_names);
         }
-
-        // if all URLs fail, wait before trying again
-        if let Some(e) = last_error {
-            error!(error = ?e, "All WebSocket URLs failed, retrying in 3 seconds");
-            time::sleep(Duration::from_secs(3)).await;
+        // command line and environment values apply to every provider in the file
+        let max_feeds_per_connection = get_max_feeds_per_connection(
+            args.max_feeds_per_connection
+                .or(provider.max_feeds_per_connection),
+            chain_pusher.max_feeds_per_connection(),
+        );
+        let redundant = get_redundant(args.redundant || provider.redundant.unwrap_or(false));
+        let delivery_format =
+            get_delivery_format(args.delivery_format.or(provider.delivery_format));
+        for group in groups {
+            let channel = get_channel(
+                args.channel.clone(),
+                group.channel.or(provider.channel.clone()),
+                &group.name,
+            );
+            let group = Arc::new(FeedGroup::new(
+                group.name,
+                chain_pusher.clone(),
+                group.feeds,
+                max_feeds_per_connection,
+            ));
+            info!(
+                feed_group = %group.name,
+                provider = chain_pusher.provider_name(),
+                urls = ?provider.urls,
+                channel = %channel,
+                connections = group.connections().len(),
+                feeds = ?group.feeds(),
+                "Starting feed group"
+            );
+            for subscriptions in group.connections() {
+                let settings = ConnectionSettings {
+                    credentials: credentials.clone(),
+                    subscriptions: subscriptions.clone(),
+                    channel: channel.clone(),
+                    delivery_format,
+                    tls: tls.clone(),
+                    proxy: proxy.clone(),
+                    feed_stale_after,
+                    feed_stale_reconnect,
+                    shutdown: shutdown_rx.clone(),
+                };
+                if redundant {
+                    clients.spawn(run_redundant_websocket_clients(
+                        chain_pusher.clone(),
+                        provider.urls.clone(),
+                        settings,
+                        reconnect_policy.clone(),
+                        endpoint_health.clone(),
+                    ));
+                } else {
+                    clients.spawn(run_failover_websocket_clients(
+                        chain_pusher.clone(),
+                        provider.urls.clone(),
+                        settings,
+                        reconnect_policy.clone(),
+                        endpoint_health.clone(),
+                    ));
+                }
+            }
+            feed_groups.push(group);
         }
     }
-}
-
-async fn run_websocket_client(
-    chain_pusher: &Arc<dyn ChainPusher>,
-    url: &str,
-    auth_header: &str,
-    price_feeds: &[String],
-    channel: &str,
-    tls_connector: TlsConnector,
-) -> Result<(), Box<dyn std::error::Error>> {
-    info!(url = %url, "Establishing WebSocket connection");
-
-    let url = Url::parse(url)?;
-    let host = url.host_str().ok_or("Missing host in URL")?;
-    let address = format!("{}:{}", host, url.port().unwrap_or(443));
-    let stream = TcpStream::connect(address).await?;
-
-    let mut request = url.clone().try_into_request()?;
-    request
-        .headers_mut()
-        .insert("AUTHORIZATION", HeaderValue::from_str(auth_header)?);
-
-    let stream: Box<dyn WebSocketStream> = if url.scheme() == "wss" {
-        Box::new(tls_connector.connect(host, stream).await?)
-    } else {
-        Box::new(stream)
-    };
-
-    let upgraded = WebSocketClientBuilder::default()
-        .extension(DeflateExtProvider::default())
-        .subscribe(stream, request)
-        .await?;
-
-    let UpgradedClient { mut websocket, .. } = upgraded;
-    info!("WebSocket connected.");
 
-    let mut buf = BytesMut::new();
-    let message_text = chain_pusher
-        .feeds_subscription_msg(price_feeds, channel)
-        .await?;
-
-    info!(message = %message_text, "Subscribing to price feeds");
-
-    websocket
-        .write(message_text.as_bytes(), PayloadType::Text)
-        .await?;
-
-    info!("Subscribed to price feeds.");
-
-    loop {
-        buf.clear();
-        let res = time::timeout(Duration::from_secs(30), websocket.read(&mut buf)).await;
-        match res {
-            Ok(Ok(message)) => match message {
-                Message::Text => {
-                    if let Err(e) = chain_pusher
-                        .process_update(&String::from_utf8_lossy(&buf))
-                        .await
-                    {
-                        warn!(error = ?e, message = %String::from_utf8_lossy(&buf), "Failed to parse price update")
-                    } else {
-                        debug!("Processed price updates");
-                    }
-                }
-                Message::Close(_) => return Err("WebSocket closed".into()),
-                Message::Ping(payload) => {
-                    websocket.write(&payload, PayloadType::Pong).await?;
-                }
-                Message::Pong(_) => {
-                    debug!("Received pong");
-                }
-                _ => {}
-            },
-            Ok(Err(e)) => return Err(e.into()),
-            Err(_) => {
-                debug!("Sending ping");
-                websocket.write(&[], PayloadType::Ping).await?;
+    if let Some(admin_socket) = get_admin_socket(args.admin_socket) {
+        tokio::spawn(async move {
+            if let Err(e) = run_admin_socket(admin_socket, feed_groups, payers).await {
+                error!(error = ?e, "Admin socket stopped");
             }
+        });
+    }
+
+    while let Some(result) = clients.join_next().await {
+        if let Err(e) = result {
+            error!(error = ?e, "Provider connections stopped unexpectedly");
         }
     }
+
+    info!(
+        timeout_secs = shutdown_timeout.as_secs(),
+        "WebSocket connections closed, waiting for in-flight transactions"
+    );
+    let summary = sender.drain(shutdown_timeout).await;
+    info!(
+        sent = summary.sent,
+        failed = summary.failed,
+        abandoned = summary.abandoned,
+        "Shutdown complete"
+    );
+    sender.log_delivery_stats();
 }
@@ -0,0 +1,162 @@
+use crate::args::PayerSelection;
+use crate::types::UpdateData;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::time::{self, Duration};
+use tracing::{info, warn};
+
+const BALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The keypairs that sign and pay for update transactions. Spreading transactions over several
+/// payers avoids every transaction write-locking the same fee payer account. Every key must be
+/// one of the identities the oracle program authorizes.
+pub struct PayerPool {
+    payers: RwLock<Vec<Arc<Keypair>>>,
+    selection: PayerSelection,
+    next: AtomicUsize,
+}
+
+impl PayerPool {
+    pub fn new(payers: Vec<Keypair>, selection: PayerSelection) -> Self {
+        assert!(!payers.is_empty(), "At least one payer is required");
+        PayerPool {
+            payers: RwLock::new(payers.into_iter().map(Arc::new).collect()),
+            selection,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn pubkeys(&self) -> Vec<Pubkey> {
+        self.payers
+            .read()
+            .unwrap()
+            .iter()
+            .map(|payer| payer.pubkey())
+            .collect()
+    }
+
+    /// Splits `updates` into transactions with `split` and picks the payer of each one: every
+    /// transaction goes to the next payer in turn with round-robin, or each feed always goes
+    /// to the same payer with feed sharding. `split` gets the pubkey of the payer the updates
+    /// are sized for.
+    pub fn assign(
+        &self,
+        provider: &str,
+        updates: Vec<UpdateData>,
+        split: impl Fn(&Pubkey, &[UpdateData]) -> Vec<Vec<UpdateData>>,
+    ) -> Vec<(Arc<Keypair>, Vec<UpdateData>)> {
+        let payers = self.payers.read().unwrap();
+        match self.selection {
+            PayerSelection::RoundRobin => {
+                // every payer key has the same size, so any of them sizes the transactions
+                split(&payers[0].pubkey(), &updates)
+                    .into_iter()
+                    .map(|batch| {
+                        let index = self.next.fetch_add(1, Ordering::Relaxed) % payers.len();
+                        (payers[index].clone(), batch)
+                    })
+                    .collect()
+            }
+            PayerSelection::FeedShard => {
+                let mut shards: Vec<(usize, Vec<UpdateData>)> = vec![];
+                for update in updates {
+                    let index = shard(provider, &update.symbol, payers.len());
+                    match shards.iter_mut().find(|(shard, _)| *shard == index) {
+                        Some((_, updates)) => updates.push(update),
+                        None => shards.push((index, vec![update])),
+                    }
+                }
+                shards
+                    .into_iter()
+                    .flat_map(|(index, updates)| {
+                        let payer = &payers[index];
+                        split(&payer.pubkey(), &updates)
+                            .into_iter()
+                            .map(|batch| (payer.clone(), batch))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Adds a payer, for rotating in a new key. Returns `false` if it is already in the pool.
+    pub fn add(&self, payer: Keypair) -> bool {
+        let mut payers = self.payers.write().unwrap();
+        if payers.iter().any(|known| known.pubkey() == payer.pubkey()) {
+            return false;
+        }
+        info!(payer = %payer.pubkey(), "Payer added");
+        payers.push(Arc::new(payer));
+        true
+    }
+
+    /// Removes a payer. Transactions it already signed are still sent and retried by it.
+    pub fn remove(&self, pubkey: &Pubkey) -> Result<(), Box<dyn Error>> {
+        let mut payers = self.payers.write().unwrap();
+        let index = payers
+            .iter()
+            .position(|payer| payer.pubkey() == *pubkey)
+            .ok_or("Unknown payer")?;
+        if payers.len() == 1 {
+            return Err("Cannot remove the last payer".into());
+        }
+        payers.remove(index);
+        info!(payer = %pubkey, "Payer removed");
+        Ok(())
+    }
+
+    /// Logs the balance of every payer once a minute, warning about the ones below
+    /// `min_balance` lamports.
+    pub fn spawn_balance_monitor(self: Arc<Self>, rpc_client: Arc<RpcClient>, min_balance: u64) {
+        tokio::spawn(async move {
+            let mut checks = time::interval(BALANCE_CHECK_INTERVAL);
+            loop {
+                checks.tick().await;
+                let balances = self.balances(&rpc_client).await;
+                for (payer, balance) in balances {
+                    match balance {
+                        Some(balance) if balance < min_balance => {
+                            warn!(payer = %payer, balance, min_balance, "Payer balance is low")
+                        }
+                        Some(balance) => info!(payer = %payer, balance, "Payer balance"),
+                        None => {}
+                    }
+                }
+            }
+        });
+    }
+
+    async fn balances(&self, rpc_client: &RpcClient) -> HashMap<Pubkey, Option<u64>> {
+        let mut balances = HashMap::new();
+        for payer in self.pubkeys() {
+            let balance = match rpc_client.get_balance(&payer).await {
+                Ok(balance) => Some(balance),
+                Err(e) => {
+                    warn!(payer = %payer, error = %e, "Failed to fetch payer balance");
+                    None
+                }
+            };
+            balances.insert(payer, balance);
+        }
+        balances
+    }
+}
+
+/// Parses a base58 encoded keypair, like `Keypair::from_base58_string` but without panicking.
+pub fn parse_keypair(key: &str) -> Result<Keypair, Box<dyn Error>> {
+    let bytes = solana_sdk::bs58::decode(key.trim()).into_vec()?;
+    Ok(Keypair::from_bytes(&bytes)?)
+}
+
+fn shard(provider: &str, symbol: &str, payers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    (provider, symbol).hash(&mut hasher);
+    hasher.finish() as usize % payers
+}
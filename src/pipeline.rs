@@ -0,0 +1,269 @@
+use crate::types::UpdateData;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Limits of the queue between parsing and sending.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Feeds that can have an update waiting to be sent at the same time. Parsing waits once
+    /// the queue is full.
+    pub queue_capacity: usize,
+    pub max_concurrent_sends: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            queue_capacity: 1024,
+            max_concurrent_sends: 16,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QueueMetrics {
+    pub depth: usize,
+    pub max_depth: usize,
+    /// Pending updates replaced by a newer price for the same feed.
+    pub conflated: u64,
+    /// Updates older than the one already pending for their feed.
+    pub dropped: u64,
+}
+
+type FeedKey = (String, String);
+
+#[derive(Default)]
+struct Pending {
+    order: VecDeque<FeedKey>,
+    updates: HashMap<FeedKey, UpdateData>,
+    max_depth: usize,
+}
+
+/// Holds at most one pending update per provider and feed: a newer price replaces the one still
+/// waiting, so a slow RPC never makes the pusher send prices out of date.
+pub struct SendQueue {
+    capacity: usize,
+    pending: Mutex<Pending>,
+    ready: Notify,
+    space: Notify,
+    conflated: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl SendQueue {
+    pub fn new(capacity: usize) -> Self {
+        SendQueue {
+            capacity: capacity.max(1),
+            pending: Mutex::new(Pending::default()),
+            ready: Notify::new(),
+            space: Notify::new(),
+            conflated: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `updates`, waiting for room when a new feed arrives while the queue is full.
+    pub async fn push(&self, provider: &str, updates: Vec<UpdateData>) {
+        for update in updates {
+            let key = (provider.to_string(), update.symbol.clone());
+            loop {
+                let space = self.space.notified();
+                tokio::pin!(space);
+                space.as_mut().enable();
+
+                if self.try_insert(&key, &update) {
+                    break;
+                }
+                space.await;
+            }
+            self.ready.notify_one();
+        }
+    }
+
+    /// Inserts or conflates an update. Returns `false` if the queue has no room for its feed.
+    fn try_insert(&self, key: &FeedKey, update: &UpdateData) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(queued) = pending.updates.get_mut(key) {
+            if update.temporal_numeric_value.timestamp_ns
+                > queued.temporal_numeric_value.timestamp_ns
+            {
+                *queued = update.clone();
+                self.conflated.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            return true;
+        }
+        if pending.updates.len() >= self.capacity {
+            return false;
+        }
+        pending.order.push_back(key.clone());
+        pending.updates.insert(key.clone(), update.clone());
+        pending.max_depth = pending.max_depth.max(pending.updates.len());
+        true
+    }
+
+    /// Waits for pending updates and takes all of them, grouped by provider in arrival order.
+    pub async fn pop_all(&self) -> Vec<(String, Vec<UpdateData>)> {
+        loop {
+            let batches = self.take_pending();
+            if !batches.is_empty() {
+                self.space.notify_waiters();
+                return batches;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    fn take_pending(&self) -> Vec<(String, Vec<UpdateData>)> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut batches: Vec<(String, Vec<UpdateData>)> = vec![];
+        while let Some(key) = pending.order.pop_front() {
+            let Some(update) = pending.updates.remove(&key) else {
+                continue;
+            };
+            match batches.iter_mut().find(|(provider, _)| *provider == key.0) {
+                Some((_, updates)) => updates.push(update),
+                None => batches.push((key.0, vec![update])),
+            }
+        }
+        batches
+    }
+
+    pub fn depth(&self) -> usize {
+        self.pending.lock().unwrap().updates.len()
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        let pending = self.pending.lock().unwrap();
+        QueueMetrics {
+            depth: pending.updates.len(),
+            max_depth: pending.max_depth,
+            conflated: self.conflated.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time;
+
+    fn prices(batch: &[UpdateData]) -> Vec<(&str, i128)> {
+        batch
+            .iter()
+            .map(|u| (u.symbol.as_str(), u.temporal_numeric_value.quantized_value))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn newer_price_replaces_the_pending_one() {
+        let queue = SendQueue::new(8);
+        queue
+            .push(
+                "pyth-lazer",
+                vec![UpdateData::test("1", 100, 1), UpdateData::test("2", 200, 1)],
+            )
+            .await;
+        queue
+            .push("pyth-lazer", vec![UpdateData::test("1", 101, 2)])
+            .await;
+
+        let metrics = queue.metrics();
+        assert_eq!(metrics.depth, 2);
+        assert_eq!(metrics.conflated, 1);
+        assert_eq!(metrics.dropped, 0);
+
+        let batches = queue.pop_all().await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].0, "pyth-lazer");
+        // the replaced feed keeps its place in the queue
+        assert_eq!(prices(&batches[0].1), [("1", 101), ("2", 200)]);
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn older_price_is_dropped() {
+        let queue = SendQueue::new(8);
+        queue
+            .push("pyth-lazer", vec![UpdateData::test("1", 101, 2)])
+            .await;
+        queue
+            .push("pyth-lazer", vec![UpdateData::test("1", 100, 1)])
+            .await;
+        queue
+            .push("pyth-lazer", vec![UpdateData::test("1", 102, 2)])
+            .await;
+
+        let metrics = queue.metrics();
+        assert_eq!(metrics.conflated, 0);
+        assert_eq!(metrics.dropped, 2);
+        let batches = queue.pop_all().await;
+        assert_eq!(prices(&batches[0].1), [("1", 101)]);
+    }
+
+    #[tokio::test]
+    async fn feeds_are_kept_apart_per_provider() {
+        let queue = SendQueue::new(8);
+        queue
+            .push("pyth-lazer", vec![UpdateData::test("1", 100, 1)])
+            .await;
+        queue
+            .push("stork", vec![UpdateData::test("1", 200, 1)])
+            .await;
+
+        assert_eq!(queue.metrics().conflated, 0);
+        let batches = queue.pop_all().await;
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].0, "pyth-lazer");
+        assert_eq!(prices(&batches[0].1), [("1", 100)]);
+        assert_eq!(batches[1].0, "stork");
+        assert_eq!(prices(&batches[1].1), [("1", 200)]);
+    }
+
+    #[tokio::test]
+    async fn push_waits_at_capacity() {
+        let queue = Arc::new(SendQueue::new(2));
+        queue
+            .push(
+                "pyth-lazer",
+                vec![UpdateData::test("1", 100, 1), UpdateData::test("2", 200, 1)],
+            )
+            .await;
+
+        // a pending feed still conflates when the queue is full
+        time::timeout(
+            Duration::from_secs(1),
+            queue.push("pyth-lazer", vec![UpdateData::test("1", 101, 2)]),
+        )
+        .await
+        .expect("conflating push should not wait");
+
+        let pusher = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                queue
+                    .push("pyth-lazer", vec![UpdateData::test("3", 300, 1)])
+                    .await
+            }
+        });
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!pusher.is_finished());
+        assert_eq!(queue.depth(), 2);
+
+        let batches = queue.pop_all().await;
+        assert_eq!(prices(&batches[0].1), [("1", 101), ("2", 200)]);
+        time::timeout(Duration::from_secs(1), pusher)
+            .await
+            .expect("push should resume once the queue has room")
+            .unwrap();
+        let batches = queue.pop_all().await;
+        assert_eq!(prices(&batches[0].1), [("3", 300)]);
+        assert_eq!(queue.metrics().max_depth, 2);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::args::PreflightMode;
+use crate::types::UpdateData;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_client::rpc_response::RpcSimulateTransactionResult;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Which update transactions are checked before they land, so program errors such as
+/// `Unauthorized` or a missing feed account show up in the logs.
+#[derive(Debug, Clone, Copy)]
+pub struct PreflightConfig {
+    pub mode: PreflightMode,
+    /// Also checks every Nth transaction. The first transaction of each feed is always checked.
+    pub sample_every: Option<u64>,
+}
+
+pub struct PreflightSampler {
+    config: PreflightConfig,
+    transactions: AtomicU64,
+    checked_feeds: Mutex<HashSet<(String, String)>>,
+}
+
+impl PreflightSampler {
+    pub fn new(config: PreflightConfig) -> Self {
+        PreflightSampler {
+            config,
+            transactions: AtomicU64::new(0),
+            checked_feeds: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// How the transaction carrying `updates` should be checked, if at all.
+    pub fn sample(&self, provider: &str, updates: &[UpdateData]) -> PreflightMode {
+        if self.config.mode == PreflightMode::Off {
+            return PreflightMode::Off;
+        }
+        let transaction = self.transactions.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut checked_feeds = self.checked_feeds.lock().unwrap();
+        let mut first_for_feed = false;
+        for update in updates {
+            first_for_feed |= checked_feeds.insert((provider.to_string(), update.symbol.clone()));
+        }
+        let sampled = self
+            .config
+            .sample_every
+            .is_some_and(|every| transaction.is_multiple_of(every));
+        if first_for_feed || sampled {
+            self.config.mode
+        } else {
+            PreflightMode::Off
+        }
+    }
+}
+
+/// Logs the outcome of simulating an update transaction, with the program logs when it failed.
+pub fn log_simulation(provider: &str, feeds: &[&str], result: &RpcSimulateTransactionResult) {
+    match &result.err {
+        Some(err) => warn!(
+            provider,
+            feeds = ?feeds,
+            error = %err,
+            logs = ?result.logs.as_deref().unwrap_or_default(),
+            "Simulated update transaction failed"
+        ),
+        None => info!(
+            provider,
+            feeds = ?feeds,
+            units_consumed = ?result.units_consumed,
+            "Simulated update transaction succeeded"
+        ),
+    }
+}
+
+/// The simulation result of a send rejected by preflight checks.
+pub fn preflight_failure(err: &ClientError) -> Option<&RpcSimulateTransactionResult> {
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => Some(result),
+        _ => None,
+    }
+}
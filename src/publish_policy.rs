@@ -0,0 +1,371 @@
+use crate::types::UpdateData;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+/// When a new price for a feed is worth an on-chain write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PublishPolicy {
+    /// Smallest move since the last published price that is published. `0` publishes every tick.
+    pub deviation_bps: u32,
+    /// Publishes anyway once the last published price is this old, keeping `publish_time` fresh.
+    pub heartbeat: Option<Duration>,
+}
+
+impl PublishPolicy {
+    /// Parses `<deviation_bps>:<heartbeat_secs>`, where a heartbeat of `0` disables it.
+    fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        let (deviation_bps, heartbeat_secs) = value
+            .split_once(':')
+            .ok_or("Expected <deviation_bps>:<heartbeat_secs>")?;
+        let heartbeat_secs: u64 = heartbeat_secs.trim().parse()?;
+        Ok(PublishPolicy {
+            deviation_bps: deviation_bps.trim().parse()?,
+            heartbeat: (heartbeat_secs > 0).then(|| Duration::from_secs(heartbeat_secs)),
+        })
+    }
+
+    fn publishes_every_tick(&self) -> bool {
+        self.deviation_bps == 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PublishPolicyConfig {
+    pub default: PublishPolicy,
+    /// Overrides keyed by feed name, as feeds are subscribed.
+    pub per_feed: HashMap<String, PublishPolicy>,
+}
+
+impl PublishPolicyConfig {
+    /// Parses `<feed>=<deviation_bps>:<heartbeat_secs>` overrides on top of `default`.
+    pub fn parse(default: PublishPolicy, entries: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut per_feed = HashMap::new();
+        for entry in entries {
+            let (feed, policy) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <feed>=<policy>, got '{}'", entry))?;
+            per_feed.insert(feed.trim().to_string(), PublishPolicy::parse(policy)?);
+        }
+        Ok(PublishPolicyConfig { default, per_feed })
+    }
+}
+
+/// Drops updates whose price barely moved since the last published one, unless the feed is due
+/// for a heartbeat.
+pub struct PublishFilter {
+    config: PublishPolicyConfig,
+    /// Feed names keyed by the symbol their updates carry, for providers whose symbols differ
+    /// from the names overrides are configured with. Pyth Lazer symbols are numeric ids and
+    /// Stork symbols are the names themselves, so the providers can share one map.
+    feed_names: RwLock<HashMap<String, String>>,
+    /// Price and timestamp of the last published update per provider and feed.
+    last_published: Mutex<HashMap<(String, String), (i128, u64)>>,
+    skipped: AtomicU64,
+}
+
+impl PublishFilter {
+    pub fn new(config: PublishPolicyConfig) -> Self {
+        PublishFilter {
+            config,
+            feed_names: RwLock::new(HashMap::new()),
+            last_published: Mutex::new(HashMap::new()),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    /// Feeds with a policy of their own.
+    pub fn overridden_feeds(&self) -> Vec<String> {
+        self.config.per_feed.keys().cloned().collect()
+    }
+
+    /// Records the feed name behind each update symbol (symbol to feed name).
+    pub fn name_feeds(&self, feed_names: HashMap<String, String>) {
+        self.feed_names.write().unwrap().extend(feed_names);
+    }
+
+    pub fn retain_publishable(
+        &self,
+        provider: &str,
+        mut updates: Vec<UpdateData>,
+    ) -> Vec<UpdateData> {
+        let feed_names = self.feed_names.read().unwrap();
+        let mut last_published = self.last_published.lock().unwrap();
+        updates.retain(|update| {
+            let feed = feed_names.get(&update.symbol).unwrap_or(&update.symbol);
+            let policy = self
+                .config
+                .per_feed
+                .get(feed)
+                .unwrap_or(&self.config.default);
+            let price = update.temporal_numeric_value.quantized_value;
+            let timestamp_ns = update.temporal_numeric_value.timestamp_ns;
+            let key = (provider.to_string(), update.symbol.clone());
+
+            let publish = policy.publishes_every_tick()
+                || last_published
+                    .get(&key)
+                    .is_none_or(|&(last_price, last_timestamp_ns)| {
+                        moved_enough(last_price, price, policy.deviation_bps)
+                            || policy.heartbeat.is_some_and(|heartbeat| {
+                                timestamp_ns.saturating_sub(last_timestamp_ns)
+                                    >= heartbeat.as_nanos() as u64
+                            })
+                    });
+            if publish {
+                last_published.insert(key, (price, timestamp_ns));
+            } else {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+            }
+            publish
+        });
+        updates
+    }
+
+    /// Forgets the published prices of updates that were given up without landing, so the
+    /// next price of their feeds is published however little it moved.
+    pub fn forget(&self, provider: &str, updates: &[UpdateData]) {
+        let mut last_published = self.last_published.lock().unwrap();
+        for update in updates {
+            let key = (provider.to_string(), update.symbol.clone());
+            let given_up = (
+                update.temporal_numeric_value.quantized_value,
+                update.temporal_numeric_value.timestamp_ns,
+            );
+            if last_published.get(&key) == Some(&given_up) {
+                last_published.remove(&key);
+            }
+        }
+    }
+
+    /// Updates not published because the price didn't move enough.
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+}
+
+fn moved_enough(last_price: i128, price: i128, deviation_bps: u32) -> bool {
+    if last_price == 0 {
+        return price != 0;
+    }
+    let moved = (price - last_price).unsigned_abs();
+    moved.saturating_mul(10_000)
+        >= last_price
+            .unsigned_abs()
+            .saturating_mul(deviation_bps as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    fn filter(deviation_bps: u32, heartbeat_secs: u64) -> PublishFilter {
+        PublishFilter::new(PublishPolicyConfig {
+            default: PublishPolicy {
+                deviation_bps,
+                heartbeat: (heartbeat_secs > 0).then(|| Duration::from_secs(heartbeat_secs)),
+            },
+            per_feed: HashMap::new(),
+        })
+    }
+
+    /// Whether `update` is published, feeding it through the filter.
+    fn publishes(filter: &PublishFilter, update: UpdateData) -> bool {
+        !filter
+            .retain_publishable("pyth-lazer", vec![update])
+            .is_empty()
+    }
+
+    #[test]
+    fn publishes_once_the_price_moved_by_the_deviation() {
+        let filter = filter(50, 0);
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_049, 2 * SECOND_NS)
+        ));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 9_951, 3 * SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_050, 4 * SECOND_NS)
+        ));
+        // measured from the last published price, not the last received one
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_100, 5 * SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 9_999, 6 * SECOND_NS)
+        ));
+        assert_eq!(filter.skipped(), 3);
+    }
+
+    #[test]
+    fn publishes_a_small_move_after_a_failed_update() {
+        let filter = filter(50, 0);
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_100, 2 * SECOND_NS)
+        ));
+        filter.forget(
+            "pyth-lazer",
+            &[UpdateData::test("1", 10_100, 2 * SECOND_NS)],
+        );
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_110, 3 * SECOND_NS)
+        ));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_120, 4 * SECOND_NS)
+        ));
+    }
+
+    #[test]
+    fn forgetting_a_superseded_update_keeps_the_newer_one() {
+        let filter = filter(50, 0);
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_100, 2 * SECOND_NS)
+        ));
+        filter.forget("pyth-lazer", &[UpdateData::test("1", 10_000, SECOND_NS)]);
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_110, 3 * SECOND_NS)
+        ));
+    }
+
+    #[test]
+    fn zero_deviation_publishes_every_tick() {
+        let filter = filter(0, 0);
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_000, 2 * SECOND_NS)
+        ));
+        assert_eq!(filter.skipped(), 0);
+    }
+
+    #[test]
+    fn publishes_a_flat_price_once_the_heartbeat_expires() {
+        let filter = filter(100, 10);
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_000, 10 * SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_000, 11 * SECOND_NS)
+        ));
+        // the heartbeat restarts from the update it published
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", 10_000, 20 * SECOND_NS)
+        ));
+    }
+
+    #[test]
+    fn zero_previous_price_publishes_any_non_zero_price() {
+        let filter = filter(50, 0);
+        assert!(publishes(&filter, UpdateData::test("1", 0, SECOND_NS)));
+        assert!(!publishes(&filter, UpdateData::test("1", 0, 2 * SECOND_NS)));
+        assert!(publishes(&filter, UpdateData::test("1", 1, 3 * SECOND_NS)));
+    }
+
+    #[test]
+    fn negative_previous_price_uses_its_magnitude() {
+        let filter = filter(50, 0);
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", -10_000, SECOND_NS)
+        ));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("1", -10_049, 2 * SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", -10_050, 3 * SECOND_NS)
+        ));
+        // crossing zero is always a large enough move
+        assert!(publishes(&filter, UpdateData::test("1", 10, 4 * SECOND_NS)));
+    }
+
+    #[test]
+    fn per_feed_overrides_apply_by_feed_name() {
+        let config = PublishPolicyConfig::parse(
+            PublishPolicy {
+                deviation_bps: 100,
+                heartbeat: None,
+            },
+            &["BTCUSD=0:0".to_string(), "ETHUSD = 10:5".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            config.per_feed["ETHUSD"],
+            PublishPolicy {
+                deviation_bps: 10,
+                heartbeat: Some(Duration::from_secs(5)),
+            }
+        );
+        let filter = PublishFilter::new(config);
+        let mut overridden = filter.overridden_feeds();
+        overridden.sort();
+        assert_eq!(overridden, ["BTCUSD", "ETHUSD"]);
+        filter.name_feeds(HashMap::from([
+            ("1".to_string(), "BTCUSD".to_string()),
+            ("2".to_string(), "ETHUSD".to_string()),
+        ]));
+
+        // BTCUSD publishes every tick
+        assert!(publishes(&filter, UpdateData::test("1", 10_000, SECOND_NS)));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("1", 10_000, 2 * SECOND_NS)
+        ));
+        // ETHUSD needs 10 bps
+        assert!(publishes(&filter, UpdateData::test("2", 10_000, SECOND_NS)));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("2", 10_009, 2 * SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("2", 10_010, 3 * SECOND_NS)
+        ));
+        // an unnamed symbol gets the default
+        assert!(publishes(&filter, UpdateData::test("3", 10_000, SECOND_NS)));
+        assert!(!publishes(
+            &filter,
+            UpdateData::test("3", 10_050, 2 * SECOND_NS)
+        ));
+        // symbols that are names already, as with Stork, match directly
+        assert!(publishes(
+            &filter,
+            UpdateData::test("BTCUSD", 10_000, SECOND_NS)
+        ));
+        assert!(publishes(
+            &filter,
+            UpdateData::test("BTCUSD", 10_000, 2 * SECOND_NS)
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_policies() {
+        let default = PublishPolicy::default();
+        assert!(PublishPolicyConfig::parse(default, &["BTCUSD".to_string()]).is_err());
+        assert!(PublishPolicyConfig::parse(default, &["BTCUSD=5".to_string()]).is_err());
+        assert!(PublishPolicyConfig::parse(default, &["BTCUSD=x:1".to_string()]).is_err());
+    }
+}
@@ -1,73 +1,169 @@
-use crate::blockhash_cache::BlockhashCache;
-use crate::instructions::update_price_feed;
-use crate::pyth_lazer::price_parser::parse_price_update;
-use crate::types::{ChainPusher, UpdateData};
+use crate::args::DeliveryFormat;
+use crate::auth::Auth;
+use crate::dedup::UpdateDeduplicator;
+use crate::pyth_lazer::price_parser::{parse_binary_message, parse_message};
+use crate::sender::TransactionSender;
+use crate::subscriptions::FeedChange;
+use crate::types::{ChainPusher, ProviderMessage, SubscriptionIds, UpdateData};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcSendTransactionConfig;
-use solana_sdk::{
-    signature::{Keypair, Signer},
-    transaction::Transaction,
-};
-use tracing::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Every change to a Lazer subscription, including resubscribing a silent feed, resends the
+/// connection's whole feed list, so connections are kept small.
+const MAX_FEEDS_PER_CONNECTION: usize = 20;
 
 pub struct PythChainPusher {
-    rpc_client: RpcClient,
-    payer: Keypair,
+    sender: Arc<TransactionSender>,
     provider: String,
-    blockhash_cache: BlockhashCache,
+    deduplicator: UpdateDeduplicator,
     http_client: reqwest::Client,
 }
 
 #[async_trait]
 impl ChainPusher for PythChainPusher {
-    async fn new(rpc_url: &str, payer_keypair: Keypair) -> Self {
-        let rpc_client = RpcClient::new(rpc_url.to_string());
-        let rpc_clone = rpc_client.get_inner_client().clone();
-
+    fn new(sender: Arc<TransactionSender>) -> Self {
         PythChainPusher {
-            rpc_client,
-            payer: payer_keypair,
+            sender,
             provider: "pyth-lazer".to_string(),
-            blockhash_cache: BlockhashCache::new(rpc_clone).await,
+            deduplicator: UpdateDeduplicator::default(),
             http_client: reqwest::Client::new(),
         }
     }
 
+    fn provider_name(&self) -> &'static str {
+        "pyth-lazer"
+    }
+
+    fn token_auth(&self, token: String) -> Auth {
+        Auth::Bearer(token)
+    }
+
+    fn max_feeds_per_connection(&self) -> Option<usize> {
+        Some(MAX_FEEDS_PER_CONNECTION)
+    }
+
     async fn feeds_subscription_msg(
         &self,
         price_feeds: &[String],
         channel: &str,
+        delivery_format: DeliveryFormat,
+        subscription_ids: &mut SubscriptionIds,
     ) -> Result<String, Box<dyn std::error::Error>> {
+        let price_feed_ids = self.price_feed_ids(price_feeds).await?;
+        Ok(subscribe_message(
+            &price_feed_ids,
+            channel,
+            delivery_format,
+            subscription_ids.start(price_feeds),
+        ))
+    }
+
+    async fn feeds_resubscription_msgs(
+        &self,
+        _change: &FeedChange,
+        price_feeds: &[String],
+        channel: &str,
+        delivery_format: DeliveryFormat,
+        subscription_ids: &mut SubscriptionIds,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // A Lazer subscription can't be edited, so subscribe to the new feed list before
+        // dropping the old subscription. Updates received twice in between are de-duplicated.
+        let price_feed_ids = self.price_feed_ids(price_feeds).await?;
+        let previous = subscription_ids.active;
+        let mut messages = vec![];
+        if price_feed_ids.is_empty() {
+            subscription_ids.active = None;
+        } else {
+            messages.push(subscribe_message(
+                &price_feed_ids,
+                channel,
+                delivery_format,
+                subscription_ids.start(price_feeds),
+            ));
+        }
+        if let Some(previous) = previous {
+            let unsubscribe_message = serde_json::json!({
+                "type": "unsubscribe",
+                "subscriptionId": previous,
+            });
+            messages.push(serde_json::to_string(&unsubscribe_message)?);
+        }
+        Ok(messages)
+    }
+
+    async fn unknown_feeds(
+        &self,
+        price_feeds: &[String],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
         let symbols = self.get_pyth_symbols().await?;
-        let price_feed_ids: Vec<i32> = price_feeds
+        Ok(price_feeds
             .iter()
-            .filter_map(|feed| {
-                symbols
-                    .iter()
-                    .find(|symbol| symbol.name == *feed)
-                    .map(|symbol| symbol.pyth_lazer_id)
-            })
-            .collect();
-        let subscribe_message = serde_json::json!({
-            "type": "subscribe",
-            "subscriptionId": 0,
-            "priceFeedIds": price_feed_ids,
-            "properties": ["price"],
-            "chains": ["solana"],
-            "channel": channel,
-            //"channel": "real_time"
-        });
-        Ok(serde_json::to_string(&subscribe_message).expect("Failed to serialize message"))
+            .filter(|feed| !symbols.iter().any(|symbol| symbol.name == **feed))
+            .cloned()
+            .collect())
     }
 
-    async fn process_update(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let updates = parse_price_update(message)?;
-        self.send_price_updates(&updates).await
+    async fn feed_names(
+        &self,
+        price_feeds: &[String],
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+        // updates are keyed by Lazer id rather than by name
+        let symbols = self.get_pyth_symbols().await?;
+        Ok(symbols
+            .into_iter()
+            .filter(|symbol| price_feeds.contains(&symbol.name))
+            .map(|symbol| (symbol.pyth_lazer_id.to_string(), symbol.name))
+            .collect())
+    }
+
+    fn parse_message(&self, message: &str) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
+        parse_message(message)
+    }
+
+    fn parse_binary_message(
+        &self,
+        message: &[u8],
+    ) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
+        parse_binary_message(message)
+    }
+
+    async fn send_price_updates(
+        &self,
+        updates: Vec<UpdateData>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let updates = self.deduplicator.retain_fresh(updates);
+        if updates.is_empty() {
+            return Ok(());
+        }
+        self.sender
+            .send_price_updates(&self.provider, &updates)
+            .await
     }
 }
 
+fn subscribe_message(
+    price_feed_ids: &[i32],
+    channel: &str,
+    delivery_format: DeliveryFormat,
+    subscription_id: u64,
+) -> String {
+    let subscribe_message = serde_json::json!({
+        "type": "subscribe",
+        "subscriptionId": subscription_id,
+        "priceFeedIds": price_feed_ids,
+        "properties": ["price"],
+        "chains": ["solana"],
+        "channel": channel,
+        //"channel": "real_time"
+        "deliveryFormat": delivery_format.to_string(),
+        // binary updates carry everything we need in the solana payload
+        "parsed": delivery_format == DeliveryFormat::Json,
+    });
+    serde_json::to_string(&subscribe_message).expect("Failed to serialize message")
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct PythSymbol {
     pyth_lazer_id: i32,
@@ -83,38 +179,24 @@ struct PythSymbol {
 }
 
 impl PythChainPusher {
-    async fn send_price_updates(
-        &self,
-        updates: &Vec<UpdateData>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut ixs = vec![];
-        for update in updates {
-            let ix = update_price_feed(&self.payer.pubkey(), &self.provider, update);
-            ixs.push(ix);
-        }
-        let tx = Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            self.blockhash_cache.get_blockhash().await,
-        );
-
-        let options = RpcSendTransactionConfig {
-            skip_preflight: true,
-            ..Default::default()
-        };
-        let rpc_client = self.rpc_client.get_inner_client().clone();
-        tokio::spawn(async move {
-            match rpc_client.send_transaction_with_config(&tx, options).await {
-                Ok(signature) => {
-                    info!("\nTransaction sent: {}", signature);
-                }
-                Err(err) => {
-                    info!("\nTransaction error: {}", err);
-                }
-            }
-        });
-        Ok(())
+    /// Replaces the HTTP client used to look up Lazer symbols, e.g. with one that goes through
+    /// a proxy.
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    async fn price_feed_ids(&self, price_feeds: &[String]) -> Result<Vec<i32>, reqwest::Error> {
+        let symbols = self.get_pyth_symbols().await?;
+        Ok(price_feeds
+            .iter()
+            .filter_map(|feed| {
+                symbols
+                    .iter()
+                    .find(|symbol| symbol.name == *feed)
+                    .map(|symbol| symbol.pyth_lazer_id)
+            })
+            .collect())
     }
 
     async fn get_pyth_symbols(&self) -> Result<Vec<PythSymbol>, reqwest::Error> {
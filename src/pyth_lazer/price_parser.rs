@@ -1,10 +1,58 @@
-use crate::types::{SolanaMessage, TemporalNumericValue, UpdateData};
+use crate::types::{
+    ProviderMessage, SolanaMessage, TemporalNumericValue, UpdateData, SOLANA_FORMAT_MAGIC_LE,
+};
 use base64::Engine;
+use byteorder::{ReadBytesExt, BE, LE};
 use serde_json::Value;
+use std::io::Cursor;
 
-pub fn parse_price_update(message: &str) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
+const BINARY_UPDATE_FORMAT_MAGIC_LE: u32 = 461928307;
+const PAYLOAD_FORMAT_MAGIC: u32 = 2479346549;
+const PRICE_PROPERTY_ID: u8 = 0;
+
+pub fn parse_message(message: &str) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
     let value: Value = serde_json::from_str(message)?;
+    let message_type = value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .ok_or("Missing type")?;
+
+    match message_type {
+        "streamUpdated" => Ok(ProviderMessage::PriceUpdate(parse_price_update(&value)?)),
+        "subscribed" | "unsubscribed" => Ok(ProviderMessage::SubscriptionAck {
+            ignored_feed_ids: vec![],
+        }),
+        "subscribedWithInvalidFeedIdsIgnored" => {
+            // every reason (unknown, unsupported channel, unstable, ...) holds a list of feeds
+            let ignored_feed_ids = value
+                .get("ignoredInvalidFeedIds")
+                .and_then(|ignored| ignored.as_object())
+                .map(|ignored| {
+                    ignored
+                        .values()
+                        .filter_map(|ids| ids.as_array())
+                        .flatten()
+                        .map(|id| id.as_str().map_or_else(|| id.to_string(), str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(ProviderMessage::SubscriptionAck { ignored_feed_ids })
+        }
+        "subscriptionError" | "error" => Ok(ProviderMessage::SubscriptionError {
+            error: value
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("unknown error")
+                .to_string(),
+            // Lazer only reports the subscription id, the connection knows its feeds
+            feed_ids: vec![],
+            subscription_id: value.get("subscriptionId").and_then(|id| id.as_u64()),
+        }),
+        other => Ok(ProviderMessage::Other(other.to_string())),
+    }
+}
 
+fn parse_price_update(value: &Value) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
     let parsed = value
         .get("parsed")
         .ok_or("Missing parsed field")?
@@ -54,26 +102,229 @@ pub fn parse_price_update(message: &str) -> Result<Vec<UpdateData>, Box<dyn std:
             .as_str()
             .ok_or("Price is not a string")?;
 
-        let update_data = UpdateData {
-            symbol: price_feed_id.to_string(),
-            id: {
-                let mut id = [0u8; 32];
-                let bytes = price_feed_id.to_le_bytes();
-                id[..bytes.len()].copy_from_slice(&bytes);
-                id
-            },
-            temporal_numeric_value: TemporalNumericValue {
-                timestamp_ns: timestamp_us * 1000, // Convert microseconds to nanoseconds
-                quantized_value: price.parse::<i128>()?,
-            },
-            publisher_merkle_root: message.public_key,
-            r: message.signature[0..32].try_into()?,
-            s: message.signature[32..64].try_into()?,
-            ..UpdateData::default()
-        };
+        let update_data = lazer_update_data(
+            price_feed_id,
+            timestamp_us,
+            price.parse::<i128>()?,
+            &message,
+        )?;
 
         price_updates.push(update_data);
     }
 
     Ok(price_updates)
 }
+
+/// Parses a binary WebSocket update: a magic number and subscription id followed by one
+/// length-prefixed message per requested chain. Only the Solana message is used, its payload
+/// carries the same data as the `parsed` field of JSON updates.
+pub fn parse_binary_message(message: &[u8]) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
+    Ok(ProviderMessage::PriceUpdate(parse_binary_price_update(
+        message,
+    )?))
+}
+
+fn parse_binary_price_update(
+    message: &[u8],
+) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
+    let mut reader = Cursor::new(message);
+    if reader.read_u32::<LE>()? != BINARY_UPDATE_FORMAT_MAGIC_LE {
+        return Err("Binary update magic mismatch".into());
+    }
+    let _subscription_id = reader.read_u64::<BE>()?;
+
+    while (reader.position() as usize) < message.len() {
+        let len: usize = reader.read_u16::<BE>()?.into();
+        let start = reader.position() as usize;
+        let chain_message = message
+            .get(start..start + len)
+            .ok_or("Truncated binary update")?;
+        reader.set_position((start + len) as u64);
+
+        if chain_message.starts_with(&SOLANA_FORMAT_MAGIC_LE.to_le_bytes()) {
+            let solana_message = SolanaMessage::deserialize_slice(chain_message)?;
+            return parse_solana_payload(&solana_message);
+        }
+    }
+
+    Err("Missing solana message in binary update".into())
+}
+
+fn parse_solana_payload(
+    message: &SolanaMessage,
+) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
+    let mut reader = Cursor::new(message.payload.as_slice());
+    if reader.read_u32::<LE>()? != PAYLOAD_FORMAT_MAGIC {
+        return Err("Payload magic mismatch".into());
+    }
+    let timestamp_us = reader.read_u64::<LE>()?;
+    let _channel_id = reader.read_u8()?;
+    let feeds_len = reader.read_u8()?;
+
+    let mut price_updates = Vec::with_capacity(feeds_len.into());
+    for _ in 0..feeds_len {
+        let price_feed_id = reader.read_u32::<LE>()?;
+        let properties_len = reader.read_u8()?;
+        let mut price = 0;
+        for _ in 0..properties_len {
+            match reader.read_u8()? {
+                PRICE_PROPERTY_ID => price = reader.read_i64::<LE>()?,
+                property => return Err(format!("Unsupported feed property {}", property).into()),
+            }
+        }
+        // A zero price means the feed has no aggregate price for this timestamp
+        if price == 0 {
+            continue;
+        }
+        price_updates.push(lazer_update_data(
+            price_feed_id.into(),
+            timestamp_us,
+            price.into(),
+            message,
+        )?);
+    }
+
+    Ok(price_updates)
+}
+
+fn lazer_update_data(
+    price_feed_id: u64,
+    timestamp_us: u64,
+    price: i128,
+    message: &SolanaMessage,
+) -> Result<UpdateData, Box<dyn std::error::Error>> {
+    Ok(UpdateData {
+        symbol: price_feed_id.to_string(),
+        id: {
+            let mut id = [0u8; 32];
+            let bytes = price_feed_id.to_le_bytes();
+            id[..bytes.len()].copy_from_slice(&bytes);
+            id
+        },
+        temporal_numeric_value: TemporalNumericValue {
+            timestamp_ns: timestamp_us
+                .checked_mul(1000) // Convert microseconds to nanoseconds
+                .ok_or("Timestamp out of range")?,
+            quantized_value: price,
+        },
+        publisher_merkle_root: message.public_key,
+        r: message.signature[0..32].try_into()?,
+        s: message.signature[32..64].try_into()?,
+        ..UpdateData::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    const SIGNATURE: [u8; 64] = [7; 64];
+    const PUBLIC_KEY: [u8; 32] = [9; 32];
+    const TIMESTAMP_US: u64 = 1_760_000_000_123_456;
+
+    /// A binary update laid out like the frames Pyth Lazer sends for a subscription with EVM and
+    /// Solana formats: BTC/USD (feed 1) with a price, and feed 2 without one.
+    fn binary_frame() -> Vec<u8> {
+        let mut payload = vec![];
+        payload.write_u32::<LE>(PAYLOAD_FORMAT_MAGIC).unwrap();
+        payload.write_u64::<LE>(TIMESTAMP_US).unwrap();
+        payload.write_u8(1).unwrap(); // channel
+        payload.write_u8(2).unwrap(); // feeds
+        for (feed_id, price) in [(1u32, 6_712_345_678_901i64), (2, 0)] {
+            payload.write_u32::<LE>(feed_id).unwrap();
+            payload.write_u8(1).unwrap(); // properties
+            payload.write_u8(PRICE_PROPERTY_ID).unwrap();
+            payload.write_i64::<LE>(price).unwrap();
+        }
+
+        let mut solana = vec![];
+        solana.write_u32::<LE>(SOLANA_FORMAT_MAGIC_LE).unwrap();
+        solana.extend_from_slice(&SIGNATURE);
+        solana.extend_from_slice(&PUBLIC_KEY);
+        solana.write_u16::<LE>(payload.len() as u16).unwrap();
+        solana.extend_from_slice(&payload);
+
+        let evm = [0xaau8; 24];
+
+        let mut frame = vec![];
+        frame
+            .write_u32::<LE>(BINARY_UPDATE_FORMAT_MAGIC_LE)
+            .unwrap();
+        frame.write_u64::<BE>(1).unwrap(); // subscription id
+        for message in [&evm[..], &solana[..]] {
+            frame.write_u16::<BE>(message.len() as u16).unwrap();
+            frame.extend_from_slice(message);
+        }
+        frame
+    }
+
+    #[test]
+    fn parses_binary_frame() {
+        let ProviderMessage::PriceUpdate(updates) = parse_binary_message(&binary_frame()).unwrap()
+        else {
+            panic!("expected a price update");
+        };
+        // feed 2 has no price and is skipped
+        assert_eq!(updates.len(), 1);
+        let update = &updates[0];
+        assert_eq!(update.symbol, "1");
+        assert_eq!(update.id[0], 1);
+        assert_eq!(
+            update.temporal_numeric_value.timestamp_ns,
+            TIMESTAMP_US * 1000
+        );
+        assert_eq!(
+            update.temporal_numeric_value.quantized_value,
+            6_712_345_678_901
+        );
+        assert_eq!(update.publisher_merkle_root, PUBLIC_KEY);
+        assert_eq!(update.r, SIGNATURE[..32]);
+        assert_eq!(update.s, SIGNATURE[32..]);
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let frame = binary_frame();
+        for len in 0..frame.len() {
+            assert!(
+                parse_binary_message(&frame[..len]).is_err(),
+                "frame truncated to {} bytes was accepted",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_magic() {
+        let mut frame = binary_frame();
+        frame[0] ^= 0xff;
+        assert!(parse_binary_message(&frame).is_err());
+
+        // a Solana message whose payload has the wrong magic
+        let mut frame = binary_frame();
+        let payload_start = frame.len() - (4 + 8 + 1 + 1 + 2 * (4 + 1 + 1 + 8));
+        frame[payload_start] ^= 0xff;
+        assert!(parse_binary_message(&frame).is_err());
+    }
+
+    #[test]
+    fn rejects_frame_without_solana_message() {
+        let mut frame = vec![];
+        frame
+            .write_u32::<LE>(BINARY_UPDATE_FORMAT_MAGIC_LE)
+            .unwrap();
+        frame.write_u64::<BE>(1).unwrap();
+        frame.write_u16::<BE>(3).unwrap();
+        frame.extend_from_slice(&[1, 2, 3]);
+        assert!(parse_binary_message(&frame).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_timestamp() {
+        let mut frame = binary_frame();
+        let timestamp_start = frame.len() - (8 + 1 + 1 + 2 * (4 + 1 + 1 + 8));
+        frame[timestamp_start..timestamp_start + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_binary_message(&frame).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A connection that stayed up at least this long resets the backoff.
+pub const STABLE_CONNECTION: Duration = Duration::from_secs(30);
+/// Failures older than this no longer count against an endpoint.
+const FAILURE_WINDOW: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomised, between 0 (none) and 1 (full jitter).
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before reconnect attempt number `attempt` (starting at 0). The exponential delay is
+    /// capped at `max_delay` and then shortened by a random amount of up to `jitter` of itself,
+    /// so instances that lost the same endpoint don't come back in lockstep.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponential = self.initial_delay.as_secs_f64() * self.multiplier.powf(attempt as f64);
+        let capped = exponential.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+        Duration::from_secs_f64(capped * (1.0 - jitter))
+    }
+}
+
+#[derive(Default)]
+struct EndpointStats {
+    recent_failures: VecDeque<Instant>,
+    connect_latency: Option<Duration>,
+    connected_since: Option<Instant>,
+    uptime: Duration,
+}
+
+impl EndpointStats {
+    fn prune_failures(&mut self, now: Instant) {
+        while self
+            .recent_failures
+            .front()
+            .is_some_and(|failure| now.duration_since(*failure) > FAILURE_WINDOW)
+        {
+            self.recent_failures.pop_front();
+        }
+    }
+
+    fn uptime(&self, now: Instant) -> Duration {
+        self.uptime
+            + self
+                .connected_since
+                .map(|since| now.duration_since(since))
+                .unwrap_or_default()
+    }
+
+    /// Higher is healthier. Recent failures weigh the most, followed by the handshake latency;
+    /// accumulated uptime earns back a few points.
+    fn score(&mut self, now: Instant) -> f64 {
+        self.prune_failures(now);
+        let failures = self.recent_failures.len() as f64 * 20.0;
+        let latency = self
+            .connect_latency
+            .map(|latency| (latency.as_millis() as f64 / 10.0).min(30.0))
+            .unwrap_or(0.0);
+        let uptime = (self.uptime(now).as_secs_f64() / 60.0).min(20.0);
+        100.0 - failures - latency + uptime
+    }
+}
+
+/// Health bookkeeping for every WebSocket endpoint, used to pick which one to connect to first.
+#[derive(Default)]
+pub struct EndpointHealth {
+    endpoints: Mutex<HashMap<String, EndpointStats>>,
+}
+
+impl EndpointHealth {
+    pub fn record_connected(&self, url: &str, connect_latency: Duration) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(url.to_string()).or_default();
+        // Smooth the latency so a single slow handshake doesn't demote an endpoint.
+        stats.connect_latency = Some(match stats.connect_latency {
+            Some(previous) => (previous * 3 + connect_latency) / 4,
+            None => connect_latency,
+        });
+        stats.connected_since = Some(Instant::now());
+    }
+
+    /// Records a failed or dropped connection and returns how long it had been connected.
+    pub fn record_failure(&self, url: &str) -> Duration {
+        let now = Instant::now();
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let stats = endpoints.entry(url.to_string()).or_default();
+        let connected_for = stats
+            .connected_since
+            .take()
+            .map(|since| now.duration_since(since))
+            .unwrap_or_default();
+        stats.uptime += connected_for;
+        stats.recent_failures.push_back(now);
+        stats.prune_failures(now);
+        connected_for
+    }
+
+    pub fn score(&self, url: &str) -> f64 {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints
+            .entry(url.to_string())
+            .or_default()
+            .score(Instant::now())
+    }
+
+    /// Returns `urls` ordered from the healthiest to the least healthy endpoint. Endpoints with
+    /// the same score keep their configured order.
+    pub fn ranked(&self, urls: &[String]) -> Vec<String> {
+        let mut scored: Vec<(f64, &String)> =
+            urls.iter().map(|url| (self.score(url), url)).collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().map(|(_, url)| url.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter,
+        }
+    }
+
+    fn policy_without_jitter(attempt: u32) -> Duration {
+        policy(0.0).delay(attempt)
+    }
+
+    fn urls(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    #[test]
+    fn delay_grows_by_the_multiplier() {
+        let policy = policy(0.0);
+        let delays: Vec<u64> = (0..5)
+            .map(|attempt| policy.delay(attempt).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000]);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let policy = policy(0.0);
+        assert_eq!(policy.delay(5), Duration::from_secs(10));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = policy(0.5);
+        for attempt in 0..10 {
+            let capped = policy_without_jitter(attempt);
+            for _ in 0..100 {
+                let delay = policy.delay(attempt);
+                assert!(delay <= capped, "{:?} > {:?}", delay, capped);
+                assert!(delay >= capped / 2, "{:?} < {:?}", delay, capped / 2);
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_is_clamped_to_full_jitter() {
+        let policy = policy(3.0);
+        for _ in 0..100 {
+            assert!(policy.delay(20) <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn ranks_endpoints_with_recent_failures_last() {
+        let health = EndpointHealth::default();
+        let urls = urls(&["wss://a", "wss://b", "wss://c"]);
+        health.record_failure("wss://a");
+        health.record_failure("wss://b");
+        health.record_failure("wss://b");
+        assert_eq!(health.ranked(&urls), ["wss://c", "wss://a", "wss://b"]);
+    }
+
+    #[test]
+    fn ranks_faster_handshakes_first() {
+        let health = EndpointHealth::default();
+        let urls = urls(&["wss://a", "wss://b"]);
+        health.record_connected("wss://a", Duration::from_millis(300));
+        health.record_connected("wss://b", Duration::from_millis(100));
+        assert_eq!(health.ranked(&urls), ["wss://b", "wss://a"]);
+        // one slow handshake doesn't outweigh a failure
+        health.record_failure("wss://b");
+        assert_eq!(health.ranked(&urls), ["wss://a", "wss://b"]);
+    }
+
+    #[test]
+    fn equal_scores_keep_the_configured_order() {
+        let health = EndpointHealth::default();
+        let urls = urls(&["wss://c", "wss://a", "wss://b"]);
+        assert_eq!(health.ranked(&urls), urls);
+    }
+
+    #[test]
+    fn smooths_the_handshake_latency() {
+        let health = EndpointHealth::default();
+        health.record_connected("wss://a", Duration::from_millis(100));
+        health.record_failure("wss://a");
+        health.record_connected("wss://a", Duration::from_millis(500));
+        // (100 * 3 + 500) / 4 = 200ms, 20 points, plus one failure
+        assert_eq!(health.score("wss://a").round(), 60.0);
+    }
+}
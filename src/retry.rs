@@ -0,0 +1,105 @@
+use crate::types::UpdateData;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_sdk::transaction::TransactionError;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How price update transactions that failed to send or expired are sent again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts per batch of updates, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            delay: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Updates whose transaction didn't land and may be sent again with a fresh blockhash.
+#[derive(Debug)]
+pub struct Retry {
+    pub provider: String,
+    pub updates: Vec<UpdateData>,
+    /// The attempt that just failed, starting at 1.
+    pub attempt: u32,
+    pub reason: String,
+    /// Whether the RPC accepted the attempt that failed, so its updates are counted as sent
+    /// until the retry is picked up.
+    pub sent: bool,
+}
+
+/// Hands retries to the sender. Their updates count as queued until the sender picks them up,
+/// so shutdown waits for retries that are about to be sent.
+#[derive(Clone)]
+pub struct RetryQueue {
+    sender: mpsc::UnboundedSender<Retry>,
+    queued: Arc<AtomicU64>,
+}
+
+impl RetryQueue {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<Retry>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = RetryQueue {
+            sender,
+            queued: Arc::new(AtomicU64::new(0)),
+        };
+        (queue, receiver)
+    }
+
+    pub fn push(&self, retry: Retry) {
+        let updates = retry.updates.len() as u64;
+        self.queued.fetch_add(updates, Ordering::SeqCst);
+        if self.sender.send(retry).is_err() {
+            self.queued.fetch_sub(updates, Ordering::SeqCst);
+        }
+    }
+
+    /// Marks a retry as picked up, once its updates are counted as in flight.
+    pub fn picked_up(&self, retry: &Retry) {
+        self.queued
+            .fetch_sub(retry.updates.len() as u64, Ordering::SeqCst);
+    }
+
+    /// Updates waiting in retries that haven't been picked up yet.
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::SeqCst)
+    }
+}
+
+/// Transport and RPC errors are worth another attempt, errors about the transaction itself
+/// only when they are transient.
+pub fn is_retryable_send_error(err: &ClientError) -> bool {
+    if let Some(err) = err.get_transaction_error() {
+        return is_retryable_transaction_error(&err);
+    }
+    matches!(
+        err.kind(),
+        ClientErrorKind::Io(_)
+            | ClientErrorKind::Reqwest(_)
+            | ClientErrorKind::Middleware(_)
+            | ClientErrorKind::RpcError(_)
+    )
+}
+
+/// Errors caused by a stale blockhash or congestion. Program errors such as `Unauthorized`
+/// would come back the same on every attempt.
+pub fn is_retryable_transaction_error(err: &TransactionError) -> bool {
+    matches!(
+        err,
+        TransactionError::BlockhashNotFound
+            | TransactionError::AccountInUse
+            | TransactionError::ClusterMaintenance
+            | TransactionError::WouldExceedMaxBlockCostLimit
+            | TransactionError::WouldExceedMaxAccountCostLimit
+            | TransactionError::WouldExceedMaxVoteCostLimit
+            | TransactionError::WouldExceedAccountDataBlockLimit
+    )
+}
@@ -0,0 +1,739 @@
+use crate::args::PreflightMode;
+use crate::blockhash_cache::{BlockhashCache, BlockhashRefresh, SigningBlockhash};
+use crate::compute_budget::{ComputeBudget, ComputeBudgetConfig};
+use crate::confirmation::ConfirmationTracker;
+use crate::dry_run::DryRunOutput;
+use crate::instructions::{price_feed_address, update_price_feed};
+use crate::payers::PayerPool;
+use crate::pipeline::{PipelineConfig, SendQueue};
+use crate::preflight::{log_simulation, preflight_failure, PreflightConfig, PreflightSampler};
+use crate::publish_policy::{PublishFilter, PublishPolicyConfig};
+use crate::retry::{is_retryable_send_error, Retry, RetryPolicy, RetryQueue};
+use crate::rpc_endpoints::RpcEndpoints;
+use crate::types::UpdateData;
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_sdk::{
+    instruction::Instruction,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, info, warn};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts of price updates by the outcome of their last attempt, reported on shutdown. Sent
+/// updates were accepted by the RPC, whether or not they landed since.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SendSummary {
+    pub sent: u64,
+    pub failed: u64,
+    pub abandoned: u64,
+}
+
+#[derive(Default)]
+struct SendTracker {
+    in_flight_transactions: AtomicUsize,
+    in_flight_updates: AtomicU64,
+    sent_updates: AtomicU64,
+    failed_updates: AtomicU64,
+    idle: Notify,
+}
+
+impl SendTracker {
+    /// Counts updates taken off the queue until their transaction has been sent.
+    fn start_updates(&self, updates: u64) {
+        self.in_flight_updates.fetch_add(updates, Ordering::SeqCst);
+    }
+
+    fn start_transaction(&self) {
+        self.in_flight_transactions.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn finish(&self, updates: u64, success: bool) {
+        self.in_flight_transactions.fetch_sub(1, Ordering::SeqCst);
+        self.finish_updates(updates, Some(success));
+    }
+
+    /// Ends a transaction whose updates have been queued for a retry, so they count as
+    /// neither sent nor failed yet.
+    fn finish_retrying(&self, updates: u64) {
+        self.in_flight_transactions.fetch_sub(1, Ordering::SeqCst);
+        self.finish_updates(updates, None);
+    }
+
+    /// Stops counting updates as in flight, as sent or failed depending on `success` or as
+    /// neither when `None`.
+    fn finish_updates(&self, updates: u64, success: Option<bool>) {
+        match success {
+            Some(true) => self.sent_updates.fetch_add(updates, Ordering::SeqCst),
+            Some(false) => self.failed_updates.fetch_add(updates, Ordering::SeqCst),
+            None => 0,
+        };
+        if self.in_flight_updates.fetch_sub(updates, Ordering::SeqCst) == updates {
+            self.idle.notify_waiters();
+        }
+    }
+
+    /// Stops counting updates as sent because their transaction expired or failed after the
+    /// RPC accepted it, so they end up counted once, by the outcome of their last attempt.
+    fn unsend(&self, updates: u64) {
+        self.sent_updates.fetch_sub(updates, Ordering::SeqCst);
+    }
+
+    fn summary(&self) -> SendSummary {
+        SendSummary {
+            sent: self.sent_updates.load(Ordering::SeqCst),
+            failed: self.failed_updates.load(Ordering::SeqCst),
+            abandoned: self.in_flight_updates.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Groups updates into as few transactions as possible while keeping each one within the
+/// packet size limit, counting the `prefix` instructions every transaction starts with. An
+/// update that doesn't fit next to others always gets its own transaction.
+fn split_by_size(
+    payer: &Pubkey,
+    provider: &str,
+    updates: &[UpdateData],
+    prefix: &[Instruction],
+) -> Vec<Vec<UpdateData>> {
+    let mut prefix_size = TransactionSize::new(payer);
+    for ix in prefix {
+        prefix_size.add(ix);
+    }
+    let mut batches = vec![];
+    let mut batch: Vec<UpdateData> = vec![];
+    let mut size = prefix_size.clone();
+    for update in updates {
+        let ix = update_price_feed(payer, provider, update);
+        if !batch.is_empty() && size.len_with(&ix) > PACKET_DATA_SIZE {
+            batches.push(std::mem::take(&mut batch));
+            size = prefix_size.clone();
+        }
+        size.add(&ix);
+        batch.push(update.clone());
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Serialized size of a legacy transaction signed by its fee payer alone, kept up to date as
+/// instructions are added rather than serializing the whole message again for each one.
+#[derive(Clone)]
+struct TransactionSize {
+    accounts: HashSet<Pubkey>,
+    instructions: usize,
+    instruction_bytes: usize,
+}
+
+impl TransactionSize {
+    fn new(payer: &Pubkey) -> Self {
+        TransactionSize {
+            accounts: HashSet::from([*payer]),
+            instructions: 0,
+            instruction_bytes: 0,
+        }
+    }
+
+    fn add(&mut self, ix: &Instruction) {
+        self.accounts.insert(ix.program_id);
+        self.accounts
+            .extend(ix.accounts.iter().map(|account| account.pubkey));
+        self.instructions += 1;
+        self.instruction_bytes += instruction_len(ix);
+    }
+
+    /// The size once `ix` is added.
+    fn len_with(&self, ix: &Instruction) -> usize {
+        let new_accounts: HashSet<&Pubkey> = std::iter::once(&ix.program_id)
+            .chain(ix.accounts.iter().map(|account| &account.pubkey))
+            .filter(|account| !self.accounts.contains(account))
+            .collect();
+        transaction_len(
+            self.accounts.len() + new_accounts.len(),
+            self.instructions + 1,
+            self.instruction_bytes + instruction_len(ix),
+        )
+    }
+}
+
+fn transaction_len(accounts: usize, instructions: usize, instruction_bytes: usize) -> usize {
+    // signature count and the payer's signature, message header, account keys, blockhash and
+    // instructions
+    1 + 64
+        + 3
+        + compact_len(accounts)
+        + 32 * accounts
+        + 32
+        + compact_len(instructions)
+        + instruction_bytes
+}
+
+/// Compiled size of `ix`: program index, account indexes and data.
+fn instruction_len(ix: &Instruction) -> usize {
+    1 + compact_len(ix.accounts.len())
+        + ix.accounts.len()
+        + compact_len(ix.data.len())
+        + ix.data.len()
+}
+
+/// Bytes taken by the compact-u16 encoding of `len`.
+fn compact_len(len: usize) -> usize {
+    match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+/// How update transactions are queued, filtered, priced, checked and retried.
+pub struct SenderConfig {
+    pub retry_policy: RetryPolicy,
+    pub pipeline: PipelineConfig,
+    pub compute_budget: ComputeBudgetConfig,
+    pub publish_policy: PublishPolicyConfig,
+    pub preflight: PreflightConfig,
+    pub blockhash_refresh: BlockhashRefresh,
+    /// Sends pause while the cached blockhash is older than this.
+    pub max_blockhash_age: Duration,
+}
+
+/// Builds, signs and sends the `update_price_feed` transactions for every provider, keeping
+/// track of the sends still in flight so they can be drained on shutdown. Updates wait in a
+/// conflating queue until one of a limited number of send slots is free.
+pub struct TransactionSender {
+    rpc: Arc<RpcEndpoints>,
+    payers: Arc<PayerPool>,
+    blockhash_cache: BlockhashCache,
+    compute_budget: Arc<ComputeBudget>,
+    queue: SendQueue,
+    publish_filter: Arc<PublishFilter>,
+    send_permits: Arc<Semaphore>,
+    tracker: Arc<SendTracker>,
+    confirmations: Arc<ConfirmationTracker>,
+    preflight: PreflightSampler,
+    retry_policy: RetryPolicy,
+    retries: RetryQueue,
+    /// Set once shutdown starts, after which no more retries are scheduled.
+    draining: AtomicBool,
+    /// Set in dry-run mode, where transactions are written here instead of being sent.
+    dry_run: Option<DryRunOutput>,
+    /// Timestamp of the newest update sent for each provider and feed, so a retry never
+    /// overwrites a fresher price.
+    latest_sent: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl TransactionSender {
+    pub async fn new(
+        rpc_urls: &[String],
+        payers: Arc<PayerPool>,
+        config: SenderConfig,
+        dry_run: Option<DryRunOutput>,
+    ) -> Arc<Self> {
+        let rpc = Arc::new(RpcEndpoints::new(rpc_urls));
+        let (retries, retry_requests) = RetryQueue::new();
+        let publish_filter = Arc::new(PublishFilter::new(config.publish_policy));
+        let blockhash_cache = BlockhashCache::new(
+            rpc.clone(),
+            config.blockhash_refresh,
+            config.max_blockhash_age,
+        )
+        .await;
+        let sender = Arc::new(TransactionSender {
+            confirmations: ConfirmationTracker::spawn(
+                rpc.primary(),
+                blockhash_cache.clone(),
+                retries.clone(),
+                publish_filter.clone(),
+            ),
+            blockhash_cache,
+            compute_budget: ComputeBudget::spawn(config.compute_budget, rpc.primary()).await,
+            rpc,
+            payers,
+            queue: SendQueue::new(config.pipeline.queue_capacity),
+            publish_filter,
+            send_permits: Arc::new(Semaphore::new(config.pipeline.max_concurrent_sends.max(1))),
+            tracker: Arc::new(SendTracker::default()),
+            preflight: PreflightSampler::new(config.preflight),
+            retry_policy: config.retry_policy,
+            retries,
+            draining: AtomicBool::new(false),
+            dry_run,
+            latest_sent: Mutex::new(HashMap::new()),
+        });
+        tokio::spawn(sender.clone().run_retries(retry_requests));
+        tokio::spawn(sender.clone().run_dispatcher());
+
+        let reporter = sender.clone();
+        tokio::spawn(async move {
+            let mut reports = time::interval(REPORT_INTERVAL);
+            reports.tick().await;
+            loop {
+                reports.tick().await;
+                reporter.log_delivery_stats();
+            }
+        });
+        sender
+    }
+
+    pub async fn send_price_updates(
+        &self,
+        provider: &str,
+        updates: &[UpdateData],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.queue.push(provider, updates.to_vec()).await;
+        Ok(())
+    }
+
+    async fn run_dispatcher(self: Arc<Self>) {
+        loop {
+            let batches: Vec<_> = self
+                .queue
+                .pop_all()
+                .await
+                .into_iter()
+                .map(|(provider, updates)| {
+                    let updates = self.publish_filter.retain_publishable(&provider, updates);
+                    (provider, updates)
+                })
+                .filter(|(_, updates)| !updates.is_empty())
+                .collect();
+            let popped = batches
+                .iter()
+                .map(|(_, updates)| updates.len() as u64)
+                .sum();
+            self.tracker.start_updates(popped);
+            for (provider, updates) in batches {
+                self.dispatch(&provider, updates).await;
+            }
+        }
+    }
+
+    async fn dispatch(&self, provider: &str, updates: Vec<UpdateData>) {
+        self.record_sent(provider, &updates);
+        let prefix = self.compute_budget.instructions(1, &[]);
+        self.blockhash_cache.wait_until_healthy().await;
+        let blockhash = self.blockhash_cache.get_signing_blockhash();
+        let update_count = updates.len();
+        let transactions = self.payers.assign(provider, updates, |payer, updates| {
+            split_by_size(payer, provider, updates, &prefix)
+        });
+        if transactions.len() > 1 {
+            debug!(
+                updates = update_count,
+                transactions = transactions.len(),
+                "Split price updates across transactions"
+            );
+        }
+        for (payer, batch) in transactions {
+            let permit = self.send_permit().await;
+            self.send(provider.to_string(), &payer, batch, blockhash, 1, permit);
+        }
+    }
+
+    async fn send_permit(&self) -> OwnedSemaphorePermit {
+        self.send_permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Send permits are never closed")
+    }
+
+    /// Signs and sends one transaction in the background, holding `permit` until every RPC
+    /// endpoint has answered. In dry-run mode the transaction is written out instead.
+    fn send(
+        &self,
+        provider: String,
+        payer: &Keypair,
+        updates: Vec<UpdateData>,
+        blockhash: SigningBlockhash,
+        attempt: u32,
+        permit: OwnedSemaphorePermit,
+    ) {
+        let feed_accounts: Vec<Pubkey> = updates
+            .iter()
+            .map(|update| price_feed_address(&provider, &update.symbol))
+            .collect();
+        let mut ixs = self
+            .compute_budget
+            .instructions(updates.len(), &feed_accounts);
+        for update in &updates {
+            let ix = update_price_feed(&payer.pubkey(), &provider, update);
+            ixs.push(ix);
+        }
+        let tx = Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&payer.pubkey()),
+            &[payer],
+            blockhash.hash,
+        );
+        let update_count = updates.len() as u64;
+
+        if let Some(dry_run) = &self.dry_run {
+            if let Err(e) = dry_run.write(&provider, attempt, &tx) {
+                warn!(error = %e, "Failed to write dry-run transaction");
+            }
+            self.tracker.start_transaction();
+            self.tracker.finish(update_count, true);
+            return;
+        }
+
+        let check = self.preflight.sample(&provider, &updates);
+        let options = RpcSendTransactionConfig {
+            skip_preflight: check != PreflightMode::Preflight,
+            ..Default::default()
+        };
+        let rpc = self.rpc.clone();
+        let tracker = self.tracker.clone();
+        let confirmations = self.confirmations.clone();
+        let retries = self.retries.clone();
+        let publish_filter = self.publish_filter.clone();
+        tracker.start_transaction();
+        tokio::spawn(async move {
+            let feeds = || {
+                updates
+                    .iter()
+                    .map(|u| u.symbol.as_str())
+                    .collect::<Vec<_>>()
+            };
+            if check == PreflightMode::Simulate {
+                match rpc.primary().simulate_transaction(&tx).await {
+                    Ok(response) => log_simulation(&provider, &feeds(), &response.value),
+                    Err(e) => warn!(error = %e, "Failed to simulate update transaction"),
+                }
+            }
+            let result = rpc.send_transaction(tx, options, permit).await;
+            match result {
+                Ok(signature) => {
+                    info!("\nTransaction sent: {}", signature);
+                    confirmations.track(
+                        signature,
+                        provider,
+                        updates,
+                        attempt,
+                        blockhash.last_valid_block_height,
+                    );
+                    tracker.finish(update_count, true);
+                }
+                Err(err) => {
+                    info!("\nTransaction error: {}", err);
+                    if let Some(simulation) = preflight_failure(&err) {
+                        log_simulation(&provider, &feeds(), simulation);
+                    }
+                    if is_retryable_send_error(&err) {
+                        retries.push(Retry {
+                            provider,
+                            updates,
+                            attempt,
+                            reason: err.to_string(),
+                            sent: false,
+                        });
+                        tracker.finish_retrying(update_count);
+                    } else {
+                        publish_filter.forget(&provider, &updates);
+                        tracker.finish(update_count, false);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Re-signs updates that didn't land with a fresh blockhash, dropping the ones a newer
+    /// update for the same feed has superseded. The updates of a retry count as in flight from
+    /// the moment it is picked up until it is sent or given up, and no retries are scheduled
+    /// once shutdown has started.
+    async fn run_retries(self: Arc<Self>, mut requests: mpsc::UnboundedReceiver<Retry>) {
+        while let Some(retry) = requests.recv().await {
+            let update_count = retry.updates.len() as u64;
+            self.tracker.start_updates(update_count);
+            self.retries.picked_up(&retry);
+            if retry.sent {
+                self.tracker.unsend(update_count);
+            }
+            let feeds: Vec<&str> = retry.updates.iter().map(|u| u.symbol.as_str()).collect();
+            if retry.attempt >= self.retry_policy.max_attempts {
+                warn!(
+                    feeds = ?feeds,
+                    attempts = retry.attempt,
+                    reason = %retry.reason,
+                    "Giving up on price updates"
+                );
+                self.publish_filter.forget(&retry.provider, &retry.updates);
+                self.tracker.finish_updates(update_count, Some(false));
+                continue;
+            }
+            if self.draining.load(Ordering::SeqCst) {
+                warn!(feeds = ?feeds, reason = %retry.reason, "Shutting down, not retrying");
+                self.publish_filter.forget(&retry.provider, &retry.updates);
+                self.tracker.finish_updates(update_count, Some(false));
+                continue;
+            }
+
+            let sender = self.clone();
+            tokio::spawn(async move {
+                time::sleep(sender.retry_policy.delay).await;
+                let updates = sender.retain_latest(&retry.provider, retry.updates);
+                let superseded = update_count - updates.len() as u64;
+                if superseded > 0 {
+                    sender.tracker.finish_updates(superseded, None);
+                }
+                if updates.is_empty() {
+                    debug!(reason = %retry.reason, "Newer updates already sent, not retrying");
+                    return;
+                }
+                info!(
+                    attempt = retry.attempt + 1,
+                    updates = updates.len(),
+                    reason = %retry.reason,
+                    "Retrying price updates"
+                );
+                sender.blockhash_cache.refresh().await;
+                sender.blockhash_cache.wait_until_healthy().await;
+                let blockhash = sender.blockhash_cache.get_signing_blockhash();
+                let prefix = sender.compute_budget.instructions(1, &[]);
+                let transactions =
+                    sender
+                        .payers
+                        .assign(&retry.provider, updates, |payer, updates| {
+                            split_by_size(payer, &retry.provider, updates, &prefix)
+                        });
+                for (payer, updates) in transactions {
+                    let permit = sender.send_permit().await;
+                    sender.send(
+                        retry.provider.clone(),
+                        &payer,
+                        updates,
+                        blockhash,
+                        retry.attempt + 1,
+                        permit,
+                    );
+                }
+            });
+        }
+    }
+
+    fn record_sent(&self, provider: &str, updates: &[UpdateData]) {
+        let mut latest_sent = self.latest_sent.lock().unwrap();
+        for update in updates {
+            let timestamp = latest_sent
+                .entry((provider.to_string(), update.symbol.clone()))
+                .or_default();
+            *timestamp = (*timestamp).max(update.temporal_numeric_value.timestamp_ns);
+        }
+    }
+
+    fn retain_latest(&self, provider: &str, mut updates: Vec<UpdateData>) -> Vec<UpdateData> {
+        let latest_sent = self.latest_sent.lock().unwrap();
+        updates.retain(|update| {
+            latest_sent
+                .get(&(provider.to_string(), update.symbol.clone()))
+                .is_none_or(|latest| update.temporal_numeric_value.timestamp_ns >= *latest)
+        });
+        updates
+    }
+
+    /// Feeds with a publish policy of their own.
+    pub fn publish_overrides(&self) -> Vec<String> {
+        self.publish_filter.overridden_feeds()
+    }
+
+    /// Lets per-feed publish policies, configured by feed name, find the updates of feeds
+    /// whose symbol differs from their name (symbol to feed name).
+    pub fn name_feeds(&self, feed_names: HashMap<String, String>) {
+        self.publish_filter.name_feeds(feed_names);
+    }
+
+    pub fn log_delivery_stats(&self) {
+        let queue = self.queue.metrics();
+        info!(
+            queue_depth = queue.depth,
+            max_queue_depth = queue.max_depth,
+            conflated = queue.conflated,
+            dropped = queue.dropped,
+            below_deviation = self.publish_filter.skipped(),
+            in_flight_sends = self.tracker.in_flight_transactions.load(Ordering::SeqCst),
+            "Send pipeline stats"
+        );
+        let blockhash = self.blockhash_cache.stats();
+        info!(
+            healthy = self.blockhash_cache.is_healthy(),
+            age_ms = blockhash.age.as_millis() as u64,
+            remaining_blocks = blockhash.remaining_blocks,
+            refresh_failures = blockhash.refresh_failures,
+            "Blockhash stats"
+        );
+        self.rpc.log_stats();
+        self.confirmations.log_stats();
+    }
+
+    /// Waits until the queue is empty and every send in flight has completed, or `timeout` has
+    /// elapsed. Retries already scheduled are still sent, but no new ones are. Updates still
+    /// queued or in flight at that point are reported as abandoned.
+    pub async fn drain(&self, timeout: Duration) -> SendSummary {
+        self.draining.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let idle = self.tracker.idle.notified();
+            if self.tracker.in_flight_updates.load(Ordering::SeqCst) == 0
+                && self.queue.depth() == 0
+                && self.retries.queued() == 0
+            {
+                break;
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            // the queue has no notification of its own, so check it again regularly
+            let _ = time::timeout_at(
+                deadline.min(Instant::now() + Duration::from_millis(100)),
+                idle,
+            )
+            .await;
+        }
+        let mut summary = self.tracker.summary();
+        summary.abandoned += self.queue.depth() as u64 + self.retries.queued();
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+
+    const PROVIDER: &str = "pyth-lazer";
+
+    fn updates(count: usize) -> Vec<UpdateData> {
+        (0..count)
+            .map(|i| UpdateData {
+                symbol: format!("FEED{}USD", i),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn instructions(
+        payer: &Pubkey,
+        updates: &[UpdateData],
+        prefix: &[Instruction],
+    ) -> Vec<Instruction> {
+        let mut ixs = prefix.to_vec();
+        ixs.extend(
+            updates
+                .iter()
+                .map(|update| update_price_feed(payer, PROVIDER, update)),
+        );
+        ixs
+    }
+
+    /// Size of the transaction as it is actually serialized.
+    fn update_transaction_len(
+        payer: &Pubkey,
+        updates: &[UpdateData],
+        prefix: &[Instruction],
+    ) -> usize {
+        serialized_len(payer, &instructions(payer, updates, prefix))
+    }
+
+    fn serialized_len(payer: &Pubkey, ixs: &[Instruction]) -> usize {
+        let tx = Transaction::new_unsigned(solana_sdk::message::Message::new(ixs, Some(payer)));
+        1 + 64 * tx.signatures.len() + tx.message.serialize().len()
+    }
+
+    /// An instruction of a program of its own with `len` bytes of data, to pad transactions.
+    fn padding(len: usize) -> Instruction {
+        Instruction::new_with_bytes(Pubkey::new_unique(), &vec![0; len], vec![])
+    }
+
+    fn budget_prefix() -> Vec<Instruction> {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            ComputeBudgetInstruction::set_compute_unit_price(5_000),
+        ]
+    }
+
+    #[test]
+    fn incremental_size_matches_serialized_transaction() {
+        let payer = Pubkey::new_unique();
+        for prefix in [vec![], budget_prefix(), vec![padding(200)]] {
+            for count in 0..6 {
+                let ixs = instructions(&payer, &updates(count), &prefix);
+                let mut size = TransactionSize::new(&payer);
+                for (i, ix) in ixs.iter().enumerate() {
+                    assert_eq!(size.len_with(ix), serialized_len(&payer, &ixs[..=i]));
+                    size.add(ix);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn batch_exactly_at_the_limit_stays_together() {
+        let payer = Pubkey::new_unique();
+        let updates = updates(3);
+        let pad = (0..PACKET_DATA_SIZE)
+            .find(|&len| {
+                update_transaction_len(&payer, &updates, &[padding(len)]) == PACKET_DATA_SIZE
+            })
+            .expect("some padding fills the packet exactly");
+        let prefix = [padding(pad)];
+        assert_eq!(
+            update_transaction_len(&payer, &updates, &prefix),
+            PACKET_DATA_SIZE
+        );
+        let batches = split_by_size(&payer, PROVIDER, &updates, &prefix);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 3);
+
+        // one byte more and the last update moves to a transaction of its own
+        let prefix = [padding(pad + 1)];
+        let batches = split_by_size(&payer, PROVIDER, &updates, &prefix);
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn oversize_update_gets_its_own_transaction() {
+        let payer = Pubkey::new_unique();
+        let updates = updates(3);
+        // the prefix leaves too little room for even one update
+        let prefix = [padding(1100)];
+        assert!(update_transaction_len(&payer, &updates[..1], &prefix) > PACKET_DATA_SIZE);
+        let batches = split_by_size(&payer, PROVIDER, &updates, &prefix);
+        assert_eq!(batches.len(), 3);
+        for (batch, update) in batches.iter().zip(&updates) {
+            assert_eq!(batch.len(), 1);
+            assert_eq!(batch[0].symbol, update.symbol);
+        }
+    }
+
+    #[test]
+    fn compute_budget_prefix_counts_against_the_limit() {
+        let payer = Pubkey::new_unique();
+        let updates = updates(20);
+        for prefix in [vec![], budget_prefix()] {
+            let batches = split_by_size(&payer, PROVIDER, &updates, &prefix);
+            assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), updates.len());
+            let mut next = 0;
+            for batch in &batches {
+                next += batch.len();
+                assert!(update_transaction_len(&payer, batch, &prefix) <= PACKET_DATA_SIZE);
+                // each batch is as full as it can be
+                if next < updates.len() {
+                    let mut fuller = batch.clone();
+                    fuller.push(updates[next].clone());
+                    assert!(update_transaction_len(&payer, &fuller, &prefix) > PACKET_DATA_SIZE);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Resolves on SIGTERM (sent by supervisord and fly on stop) or Ctrl-C.
+pub async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
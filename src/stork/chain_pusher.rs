@@ -1,42 +1,47 @@
-use crate::blockhash_cache::BlockhashCache;
-use crate::instructions::update_price_feed;
-use crate::stork::price_parser::parse_price_update;
-use crate::types::{ChainPusher, UpdateData};
+use crate::args::DeliveryFormat;
+use crate::auth::Auth;
+use crate::dedup::UpdateDeduplicator;
+use crate::sender::TransactionSender;
+use crate::stork::price_parser::parse_message;
+use crate::subscriptions::FeedChange;
+use crate::types::{ChainPusher, ProviderMessage, SubscriptionIds, UpdateData};
 use async_trait::async_trait;
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcSendTransactionConfig;
-use solana_sdk::{
-    signature::{Keypair, Signer},
-    transaction::Transaction,
-};
-use tracing::info;
+use std::sync::Arc;
 
 pub struct StorkChainPusher {
-    rpc_client: RpcClient,
-    payer: Keypair,
+    sender: Arc<TransactionSender>,
     provider: String,
-    blockhash_cache: BlockhashCache,
+    deduplicator: UpdateDeduplicator,
 }
 
 #[async_trait]
 impl ChainPusher for StorkChainPusher {
-    async fn new(rpc_url: &str, payer_keypair: Keypair) -> Self {
-        let rpc_client = RpcClient::new(rpc_url.to_string());
-        let rpc_clone = rpc_client.get_inner_client().clone();
-
+    fn new(sender: Arc<TransactionSender>) -> Self {
         StorkChainPusher {
-            rpc_client,
-            payer: payer_keypair,
+            sender,
             provider: "stork-oracle".to_string(),
-            blockhash_cache: BlockhashCache::new(rpc_clone).await,
+            deduplicator: UpdateDeduplicator::default(),
         }
     }
 
+    fn provider_name(&self) -> &'static str {
+        "stork"
+    }
+
+    fn token_auth(&self, token: String) -> Auth {
+        Auth::Basic(token)
+    }
+
     async fn feeds_subscription_msg(
         &self,
         price_feeds: &[String],
         _channel: &str,
+        delivery_format: DeliveryFormat,
+        _subscription_ids: &mut SubscriptionIds,
     ) -> Result<String, Box<dyn std::error::Error>> {
+        if delivery_format == DeliveryFormat::Binary {
+            return Err("Stork does not support binary delivery".into());
+        }
         let subscribe_message = serde_json::json!({
             "type": "subscribe",
             "data": price_feeds,
@@ -44,39 +49,46 @@ impl ChainPusher for StorkChainPusher {
         Ok(serde_json::to_string(&subscribe_message).expect("Failed to serialize message"))
     }
 
-    async fn process_update(&self, message: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let updates = parse_price_update(message)?;
-        self.send_price_updates(&updates).await
+    async fn feeds_resubscription_msgs(
+        &self,
+        change: &FeedChange,
+        _price_feeds: &[String],
+        _channel: &str,
+        _delivery_format: DeliveryFormat,
+        _subscription_ids: &mut SubscriptionIds,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // Stork subscriptions are per asset, so only the changed feed needs a message
+        let message = match change {
+            FeedChange::Subscribe(feed) => serde_json::json!({
+                "type": "subscribe",
+                "data": [feed],
+            }),
+            FeedChange::Unsubscribe(feed) => serde_json::json!({
+                "type": "unsubscribe",
+                "data": [feed],
+            }),
+            FeedChange::Resubscribe(feeds) => serde_json::json!({
+                "type": "subscribe",
+                "data": feeds,
+            }),
+        };
+        Ok(vec![serde_json::to_string(&message)?])
+    }
+
+    fn parse_message(&self, message: &str) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
+        parse_message(message)
     }
-}
 
-impl StorkChainPusher {
     async fn send_price_updates(
         &self,
-        updates: &Vec<UpdateData>,
+        updates: Vec<UpdateData>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut ixs = vec![];
-        for update in updates {
-            let ix = update_price_feed(&self.payer.pubkey(), &self.provider, update);
-            ixs.push(ix);
+        let updates = self.deduplicator.retain_fresh(updates);
+        if updates.is_empty() {
+            return Ok(());
         }
-        let tx = Transaction::new_signed_with_payer(
-            &ixs,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            self.blockhash_cache.get_blockhash().await,
-        );
-
-        let options = RpcSendTransactionConfig {
-            skip_preflight: true,
-            ..Default::default()
-        };
-        let rpc_client = self.rpc_client.get_inner_client().clone();
-        tokio::spawn(async move {
-            if let Ok(signature) = rpc_client.send_transaction_with_config(&tx, options).await {
-                info!("\nTransaction sent: {}", signature);
-            }
-        });
-        Ok(())
+        self.sender
+            .send_price_updates(&self.provider, &updates)
+            .await
     }
 }
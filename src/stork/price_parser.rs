@@ -1,11 +1,45 @@
-use crate::types::{TemporalNumericValue, UpdateData};
+use crate::types::{ProviderMessage, TemporalNumericValue, UpdateData};
 use bigdecimal::{BigDecimal, ToPrimitive};
 use serde_json::Value;
 use std::str::FromStr;
 
-pub fn parse_price_update(message: &str) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
+pub fn parse_message(message: &str) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
     let value: Value = serde_json::from_str(message)?;
 
+    match value.get("type").and_then(|t| t.as_str()) {
+        Some("oracle_prices") | None => {
+            Ok(ProviderMessage::PriceUpdate(parse_price_update(&value)?))
+        }
+        Some("subscribe") | Some("unsubscribe") => Ok(ProviderMessage::SubscriptionAck {
+            ignored_feed_ids: vec![],
+        }),
+        Some("error") | Some("subscribe_error") | Some("invalid_message") => {
+            let error = value
+                .get("error")
+                .or_else(|| value.get("data"))
+                .map(|e| e.as_str().map_or_else(|| e.to_string(), str::to_string))
+                .unwrap_or_else(|| "unknown error".to_string());
+            let feed_ids = value
+                .get("data")
+                .and_then(|data| data.as_array())
+                .map(|assets| {
+                    assets
+                        .iter()
+                        .filter_map(|asset| asset.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(ProviderMessage::SubscriptionError {
+                error,
+                feed_ids,
+                subscription_id: None,
+            })
+        }
+        Some(other) => Ok(ProviderMessage::Other(other.to_string())),
+    }
+}
+
+fn parse_price_update(value: &Value) -> Result<Vec<UpdateData>, Box<dyn std::error::Error>> {
     let data = value
         .get("data")
         .ok_or("Missing data field")?
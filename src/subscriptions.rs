@@ -0,0 +1,61 @@
+use std::sync::RwLock;
+use tokio::sync::broadcast;
+use tracing::info;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedChange {
+    Subscribe(String),
+    Unsubscribe(String),
+    /// Feeds that are already subscribed but should be subscribed again, such as feeds that
+    /// went silent. Doesn't change the set of feeds.
+    Resubscribe(Vec<String>),
+}
+
+/// The set of feeds every connection should be subscribed to. Changes made at runtime are kept
+/// here, so reconnects pick them up, and broadcast to the live connections.
+pub struct FeedSubscriptions {
+    feeds: RwLock<Vec<String>>,
+    changes: broadcast::Sender<FeedChange>,
+}
+
+impl FeedSubscriptions {
+    pub fn new(feeds: Vec<String>) -> Self {
+        let (changes, _) = broadcast::channel(64);
+        Self {
+            feeds: RwLock::new(feeds),
+            changes,
+        }
+    }
+
+    pub fn feeds(&self) -> Vec<String> {
+        self.feeds.read().unwrap().clone()
+    }
+
+    /// Subscribe to changes before reading `feeds` so that no change can be missed in between.
+    pub fn changes(&self) -> broadcast::Receiver<FeedChange> {
+        self.changes.subscribe()
+    }
+
+    /// Applies `change` and notifies live connections. Returns `false` when the feed was already
+    /// in the requested state.
+    pub fn apply(&self, change: FeedChange) -> bool {
+        let mut feeds = self.feeds.write().unwrap();
+        let changed = match &change {
+            FeedChange::Subscribe(feed) if !feeds.contains(feed) => {
+                feeds.push(feed.clone());
+                true
+            }
+            FeedChange::Unsubscribe(feed) if feeds.contains(feed) => {
+                feeds.retain(|existing| existing != feed);
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            info!(change = ?change, "Feed subscriptions changed");
+            // no receivers just means no connection is live right now
+            let _ = self.changes.send(change);
+        }
+        changed
+    }
+}
@@ -0,0 +1,303 @@
+use crate::args::TlsBackend;
+use base64::Engine;
+use ratchet_rs::WebSocketStream;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{CertificateDer, ServerName},
+    RootCertStore,
+};
+use tracing::{info, warn};
+
+/// A SHA-256 pin on the leaf certificate a provider presents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificatePin {
+    /// Hash of the DER subject public key info, which survives renewals that keep the key.
+    Spki([u8; 32]),
+    /// Hash of the whole DER certificate.
+    Certificate([u8; 32]),
+}
+
+impl CertificatePin {
+    /// Parses `spki:<base64 sha256>` or `cert:<hex sha256>`.
+    pub fn parse(pin: &str) -> Result<Self, Box<dyn Error>> {
+        let (kind, hash) = pin
+            .split_once(':')
+            .ok_or("Expected spki:<base64 sha256> or cert:<hex sha256>")?;
+        let hash = match kind {
+            "spki" => base64::engine::general_purpose::STANDARD.decode(hash)?,
+            "cert" => hex::decode(hash)?,
+            other => return Err(format!("Unknown pin type '{}'", other).into()),
+        };
+        let hash: [u8; 32] = hash.try_into().map_err(|_| "Pin is not a SHA-256 hash")?;
+        Ok(match kind {
+            "spki" => CertificatePin::Spki(hash),
+            _ => CertificatePin::Certificate(hash),
+        })
+    }
+
+    fn matches(&self, certificate: &CertificateDer) -> Result<bool, Box<dyn Error>> {
+        Ok(match self {
+            CertificatePin::Spki(hash) => {
+                let certificate = webpki::EndEntityCert::try_from(certificate)?;
+                Sha256::digest(certificate.subject_public_key_info().as_ref()).as_slice() == hash
+            }
+            CertificatePin::Certificate(hash) => {
+                Sha256::digest(certificate.as_ref()).as_slice() == hash
+            }
+        })
+    }
+}
+
+/// Parses `host=pin` entries into the pins for each provider host. A host may be listed more
+/// than once to allow a backup key during rotation.
+pub fn parse_pins(
+    entries: &[String],
+) -> Result<HashMap<String, Vec<CertificatePin>>, Box<dyn Error>> {
+    let mut pins: HashMap<String, Vec<CertificatePin>> = HashMap::new();
+    for entry in entries {
+        let (host, pin) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <host>=<pin>, got '{}'", entry))?;
+        pins.entry(host.trim().to_string())
+            .or_default()
+            .push(CertificatePin::parse(pin.trim())?);
+    }
+    Ok(pins)
+}
+
+/// Checks that every pinned host is used by one of the provider `urls`, and only over TLS.
+/// Pins that could never be checked would otherwise leave those connections unpinned.
+pub fn check_pinned_urls(
+    pins: &HashMap<String, Vec<CertificatePin>>,
+    urls: &[String],
+) -> Result<(), Box<dyn Error>> {
+    for host in pins.keys() {
+        let mut used = false;
+        for url in urls {
+            let url = url::Url::parse(url)?;
+            if url.host_str() != Some(host.as_str()) {
+                continue;
+            }
+            if url.scheme() != "wss" {
+                return Err(format!(
+                    "TLS pins are configured for {}, but {} doesn't use TLS",
+                    host, url
+                )
+                .into());
+            }
+            used = true;
+        }
+        if !used {
+            return Err(format!(
+                "TLS pins are configured for {}, which no provider URL uses",
+                host
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+enum Connector {
+    Native(tokio_native_tls::TlsConnector),
+    Rustls(tokio_rustls::TlsConnector),
+}
+
+/// TLS setup for provider connections: the backend doing the handshake, the trusted roots and
+/// the certificate pins checked once the handshake is done.
+#[derive(Clone)]
+pub struct TlsConfig {
+    connector: Connector,
+    pins: Arc<HashMap<String, Vec<CertificatePin>>>,
+}
+
+impl TlsConfig {
+    /// Builds the connector for `backend`, trusting the system roots plus every certificate in
+    /// the PEM file at `ca_file`.
+    pub fn new(
+        backend: TlsBackend,
+        ca_file: Option<&str>,
+        pins: HashMap<String, Vec<CertificatePin>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let extra_roots = match ca_file {
+            Some(path) => {
+                let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+                let certificates =
+                    rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+                if certificates.is_empty() {
+                    return Err(format!("No certificates found in {}", path).into());
+                }
+                info!(
+                    path,
+                    count = certificates.len(),
+                    "Loaded extra root certificates"
+                );
+                certificates
+            }
+            None => vec![],
+        };
+
+        let connector = match backend {
+            TlsBackend::Native => {
+                let mut builder = native_tls::TlsConnector::builder();
+                for certificate in &extra_roots {
+                    builder.add_root_certificate(native_tls::Certificate::from_der(
+                        certificate.as_ref(),
+                    )?);
+                }
+                Connector::Native(builder.build()?.into())
+            }
+            TlsBackend::Rustls => {
+                let mut roots = RootCertStore::empty();
+                let system = rustls_native_certs::load_native_certs();
+                for error in system.errors {
+                    warn!(error = %error, "Failed to load system root certificates");
+                }
+                let (_, ignored) = roots.add_parsable_certificates(system.certs);
+                if ignored > 0 {
+                    warn!(ignored, "Skipped unparsable system root certificates");
+                }
+                for certificate in extra_roots {
+                    roots.add(certificate)?;
+                }
+                if roots.is_empty() {
+                    return Err("No root certificates available for rustls".into());
+                }
+                let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+                    rustls::crypto::ring::default_provider(),
+                ))
+                .with_safe_default_protocol_versions()?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+                Connector::Rustls(Arc::new(config).into())
+            }
+        };
+
+        Ok(TlsConfig {
+            connector,
+            pins: Arc::new(pins),
+        })
+    }
+
+    /// Performs the TLS handshake with `host` and checks the certificate it presented against
+    /// the pins configured for that host.
+    pub async fn connect(
+        &self,
+        host: &str,
+        stream: TcpStream,
+    ) -> Result<Box<dyn WebSocketStream>, Box<dyn Error>> {
+        match &self.connector {
+            Connector::Native(connector) => {
+                let stream = connector.connect(host, stream).await?;
+                let leaf = stream
+                    .get_ref()
+                    .peer_certificate()?
+                    .map(|certificate| certificate.to_der())
+                    .transpose()?
+                    .map(CertificateDer::from);
+                self.verify_pins(host, leaf.as_ref())?;
+                Ok(Box::new(stream))
+            }
+            Connector::Rustls(connector) => {
+                let server_name = ServerName::try_from(host.to_string())?;
+                let stream = connector.connect(server_name, stream).await?;
+                let leaf = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|certificates| certificates.first())
+                    .cloned();
+                self.verify_pins(host, leaf.as_ref())?;
+                Ok(Box::new(stream))
+            }
+        }
+    }
+
+    fn verify_pins(&self, host: &str, leaf: Option<&CertificateDer>) -> Result<(), Box<dyn Error>> {
+        let Some(pins) = self.pins.get(host) else {
+            return Ok(());
+        };
+        let leaf = leaf.ok_or("Provider presented no certificate")?;
+        for pin in pins {
+            if pin.matches(leaf)? {
+                return Ok(());
+            }
+        }
+        Err(format!("Certificate for {} does not match any configured pin", host).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pins(entries: &[&str]) -> Result<HashMap<String, Vec<CertificatePin>>, Box<dyn Error>> {
+        parse_pins(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+    }
+
+    fn urls(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| url.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_spki_and_cert_pins() {
+        let spki = base64::engine::general_purpose::STANDARD.encode([7u8; 32]);
+        let cert = hex::encode([9u8; 32]);
+        let pins = pins(&[
+            &format!("a.example.com=spki:{}", spki),
+            &format!(" a.example.com = cert:{}", cert),
+        ])
+        .unwrap();
+        assert_eq!(
+            pins["a.example.com"],
+            vec![
+                CertificatePin::Spki([7u8; 32]),
+                CertificatePin::Certificate([9u8; 32])
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_pins() {
+        let short = hex::encode([9u8; 31]);
+        for entry in [
+            "a.example.com".to_string(),
+            "a.example.com=spki".to_string(),
+            format!("a.example.com=sha1:{}", hex::encode([9u8; 32])),
+            "a.example.com=spki:not base64!".to_string(),
+            "a.example.com=cert:zz".to_string(),
+            format!("a.example.com=cert:{}", short),
+        ] {
+            assert!(pins(&[&entry]).is_err(), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn accepts_pins_used_over_tls() {
+        let pins = pins(&[&format!("a.example.com=cert:{}", hex::encode([9u8; 32]))]).unwrap();
+        let urls = urls(&["wss://a.example.com/ws", "ws://b.example.com/ws"]);
+        assert!(check_pinned_urls(&pins, &urls).is_ok());
+    }
+
+    #[test]
+    fn rejects_pinned_url_without_tls() {
+        let pins = pins(&[&format!("a.example.com=cert:{}", hex::encode([9u8; 32]))]).unwrap();
+        let urls = urls(&["wss://a.example.com/ws", "ws://a.example.com/backup"]);
+        let error = check_pinned_urls(&pins, &urls).unwrap_err().to_string();
+        assert!(error.contains("doesn't use TLS"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_pins_for_unused_host() {
+        let pins = pins(&[&format!("c.example.com=cert:{}", hex::encode([9u8; 32]))]).unwrap();
+        let urls = urls(&["wss://a.example.com/ws"]);
+        let error = check_pinned_urls(&pins, &urls).unwrap_err().to_string();
+        assert!(error.contains("no provider URL uses"), "{}", error);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::args::DeliveryFormat;
+use crate::auth::Auth;
+use crate::sender::TransactionSender;
+use crate::subscriptions::FeedChange;
 use async_trait::async_trait;
 use borsh::{BorshDeserialize, BorshSerialize};
 use byteorder::{ReadBytesExt, LE};
-use solana_sdk::signature::Keypair;
+use std::collections::HashMap;
 use std::io::{Cursor, Read};
+use std::sync::Arc;
 
 #[derive(BorshSerialize, BorshDeserialize, Clone, Default, Debug)]
 pub struct TemporalNumericValue {
@@ -22,19 +27,141 @@ pub struct UpdateData {
     pub v: u8,
 }
 
+#[cfg(test)]
+impl UpdateData {
+    /// An unsigned update of `symbol` to `price` at `timestamp_ns`, for tests.
+    pub fn test(symbol: &str, price: i128, timestamp_ns: u64) -> Self {
+        UpdateData {
+            symbol: symbol.to_string(),
+            temporal_numeric_value: TemporalNumericValue {
+                timestamp_ns,
+                quantized_value: price,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Ids of the subscriptions a connection made, for providers that number them, and the feeds
+/// each was made for so errors naming only the id can be traced back to feeds.
+#[derive(Debug, Default)]
+pub struct SubscriptionIds {
+    /// The subscription currently live, if any.
+    pub active: Option<u64>,
+    next: u64,
+    feeds: HashMap<u64, Vec<String>>,
+}
+
+impl SubscriptionIds {
+    /// Takes an id that wasn't used on this connection yet for a subscription to `price_feeds`
+    /// and makes it the active one. The feeds of the subscription it replaces are kept, errors
+    /// about it can still arrive.
+    pub fn start(&mut self, price_feeds: &[String]) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        let previous = self.active.replace(id);
+        self.feeds.retain(|kept, _| Some(*kept) == previous);
+        self.feeds.insert(id, price_feeds.to_vec());
+        id
+    }
+
+    /// The feeds subscription `id` was made for.
+    pub fn feeds(&self, id: u64) -> Option<&[String]> {
+        self.feeds.get(&id).map(Vec::as_slice)
+    }
+}
+
+/// A message received from a price provider, classified by its kind.
+#[derive(Debug)]
+pub enum ProviderMessage {
+    PriceUpdate(Vec<UpdateData>),
+    /// The provider accepted a subscription change. Feeds it skipped as invalid are listed.
+    SubscriptionAck {
+        ignored_feed_ids: Vec<String>,
+    },
+    SubscriptionError {
+        error: String,
+        feed_ids: Vec<String>,
+        /// For providers that report the subscription instead of its feeds.
+        subscription_id: Option<u64>,
+    },
+    Other(String),
+}
+
 #[async_trait]
-pub trait ChainPusher {
-    async fn new(rpc_url: &str, payer_keypair: Keypair) -> Self
+pub trait ChainPusher: Send + Sync {
+    fn new(sender: Arc<TransactionSender>) -> Self
     where
         Self: Sized;
 
+    /// Name used to configure credentials for every URL of this provider.
+    fn provider_name(&self) -> &'static str;
+
+    /// How a bare token configured for this provider is sent.
+    fn token_auth(&self, token: String) -> Auth;
+
+    /// Feeds one connection subscribes to unless configured otherwise. `None` puts all feeds
+    /// of a group on one connection.
+    fn max_feeds_per_connection(&self) -> Option<usize> {
+        None
+    }
+
+    /// The first subscription of a connection. Providers that number subscriptions take the id
+    /// from `subscription_ids`.
     async fn feeds_subscription_msg(
         &self,
         price_feeds: &[String],
         channel: &str,
+        delivery_format: DeliveryFormat,
+        subscription_ids: &mut SubscriptionIds,
     ) -> Result<String, Box<dyn std::error::Error>>;
 
-    async fn process_update(&self, message: &str) -> Result<(), Box<dyn std::error::Error>>;
+    /// Messages that apply `change` to a live connection. `price_feeds` is the full feed list
+    /// after the change. Providers that replace the active subscription start a new one in
+    /// `subscription_ids`, and clear it when no feed is left to subscribe to.
+    async fn feeds_resubscription_msgs(
+        &self,
+        change: &FeedChange,
+        price_feeds: &[String],
+        channel: &str,
+        delivery_format: DeliveryFormat,
+        subscription_ids: &mut SubscriptionIds,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+
+    /// The feeds in `price_feeds` this provider doesn't know, which it would silently skip when
+    /// subscribing. Providers that report unknown feeds themselves can keep the default.
+    async fn unknown_feeds(
+        &self,
+        _price_feeds: &[String],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    /// Maps the symbol carried by each feed's updates back to the feed name it was subscribed
+    /// under. Providers whose updates are keyed by feed name can keep the default.
+    async fn feed_names(
+        &self,
+        price_feeds: &[String],
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+        Ok(price_feeds
+            .iter()
+            .map(|feed| (feed.clone(), feed.clone()))
+            .collect())
+    }
+
+    fn parse_message(&self, message: &str) -> Result<ProviderMessage, Box<dyn std::error::Error>>;
+
+    fn parse_binary_message(
+        &self,
+        _message: &[u8],
+    ) -> Result<ProviderMessage, Box<dyn std::error::Error>> {
+        Err("Binary updates are not supported by this provider".into())
+    }
+
+    async fn send_price_updates(
+        &self,
+        updates: Vec<UpdateData>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -44,7 +171,7 @@ pub struct SolanaMessage {
     pub public_key: [u8; 32],
 }
 
-const SOLANA_FORMAT_MAGIC_LE: u32 = 2182742457;
+pub const SOLANA_FORMAT_MAGIC_LE: u32 = 2182742457;
 
 impl SolanaMessage {
     pub fn deserialize_slice(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
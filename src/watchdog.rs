@@ -0,0 +1,229 @@
+use crate::types::UpdateData;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// Feeds that went silent since the last check.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StaleFeeds {
+    /// Feeds that went silent for the first time. Resubscribing is cheaper than reconnecting.
+    pub resubscribe: Vec<String>,
+    /// Feeds that stayed silent after being resubscribed. Each is reported once until it
+    /// receives an update again, since some markets are legitimately quiet for hours.
+    pub still_silent: Vec<String>,
+}
+
+impl StaleFeeds {
+    /// Whether the connection should be dropped, which only happens when `reconnect_on_silence`
+    /// is set and a resubscribed feed is still silent.
+    pub fn reconnect(&self, reconnect_on_silence: bool) -> bool {
+        reconnect_on_silence && !self.still_silent.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FeedPhase {
+    Receiving,
+    Resubscribed,
+    Silent,
+}
+
+struct FeedState {
+    last_update: Instant,
+    phase: FeedPhase,
+}
+
+/// Tracks when each subscribed feed last received an update on one connection, so a provider
+/// that keeps the socket alive but stops sending a feed is noticed.
+pub struct FeedWatchdog {
+    stale_after: Duration,
+    /// Feed names keyed by the symbol their updates carry, which differs per provider.
+    feed_names: HashMap<String, String>,
+    feeds: HashMap<String, FeedState>,
+}
+
+impl FeedWatchdog {
+    pub fn new(stale_after: Duration) -> Self {
+        FeedWatchdog {
+            stale_after,
+            feed_names: HashMap::new(),
+            feeds: HashMap::new(),
+        }
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        self.stale_after
+    }
+
+    /// Starts watching `feed_names` (update symbol to feed name). Feeds already watched keep
+    /// their timers, new ones get a full threshold before they count as silent.
+    pub fn watch(&mut self, feed_names: HashMap<String, String>) {
+        self.watch_at(feed_names, Instant::now());
+    }
+
+    fn watch_at(&mut self, feed_names: HashMap<String, String>, now: Instant) {
+        self.feeds
+            .retain(|feed, _| feed_names.values().any(|name| name == feed));
+        for feed in feed_names.values() {
+            self.feeds.entry(feed.clone()).or_insert(FeedState {
+                last_update: now,
+                phase: FeedPhase::Receiving,
+            });
+        }
+        self.feed_names = feed_names;
+    }
+
+    pub fn record(&mut self, updates: &[UpdateData]) {
+        self.record_at(updates, Instant::now());
+    }
+
+    fn record_at(&mut self, updates: &[UpdateData], now: Instant) {
+        for update in updates {
+            let Some(feed) = self.feed_names.get(&update.symbol) else {
+                continue;
+            };
+            if let Some(state) = self.feeds.get_mut(feed) {
+                state.last_update = now;
+                state.phase = FeedPhase::Receiving;
+            }
+        }
+    }
+
+    pub fn check(&mut self) -> StaleFeeds {
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&mut self, now: Instant) -> StaleFeeds {
+        let mut stale = StaleFeeds::default();
+        for (feed, state) in self.feeds.iter_mut() {
+            if now.duration_since(state.last_update) < self.stale_after {
+                continue;
+            }
+            match state.phase {
+                FeedPhase::Receiving => {
+                    // give the resubscription a full threshold to take effect
+                    state.phase = FeedPhase::Resubscribed;
+                    state.last_update = now;
+                    stale.resubscribe.push(feed.clone());
+                }
+                FeedPhase::Resubscribed => {
+                    state.phase = FeedPhase::Silent;
+                    stale.still_silent.push(feed.clone());
+                }
+                FeedPhase::Silent => {}
+            }
+        }
+        stale.resubscribe.sort();
+        stale.still_silent.sort();
+        stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STALE_AFTER: Duration = Duration::from_secs(10);
+
+    fn watchdog(start: Instant) -> FeedWatchdog {
+        let mut watchdog = FeedWatchdog::new(STALE_AFTER);
+        watchdog.watch_at(
+            HashMap::from([
+                ("BTCUSD".to_string(), "btc".to_string()),
+                ("ETHUSD".to_string(), "eth".to_string()),
+            ]),
+            start,
+        );
+        watchdog
+    }
+
+    fn secs(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    fn stale(resubscribe: &[&str], still_silent: &[&str]) -> StaleFeeds {
+        StaleFeeds {
+            resubscribe: resubscribe.iter().map(|feed| feed.to_string()).collect(),
+            still_silent: still_silent.iter().map(|feed| feed.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn new_feeds_get_a_full_threshold() {
+        let start = Instant::now();
+        let mut watchdog = watchdog(start);
+        assert_eq!(watchdog.check_at(secs(start, 9)), StaleFeeds::default());
+        assert_eq!(
+            watchdog.check_at(secs(start, 10)),
+            stale(&["btc", "eth"], &[])
+        );
+    }
+
+    #[test]
+    fn resubscribes_then_reports_silence_once() {
+        let start = Instant::now();
+        let mut watchdog = watchdog(start);
+        watchdog.record_at(&[UpdateData::test("ETHUSD", 1, 0)], secs(start, 5));
+
+        assert_eq!(watchdog.check_at(secs(start, 10)), stale(&["btc"], &[]));
+        // the resubscription gets its own threshold
+        assert_eq!(watchdog.check_at(secs(start, 15)), stale(&["eth"], &[]));
+        assert_eq!(watchdog.check_at(secs(start, 20)), stale(&[], &["btc"]));
+        assert_eq!(watchdog.check_at(secs(start, 25)), stale(&[], &["eth"]));
+        assert_eq!(watchdog.check_at(secs(start, 60)), StaleFeeds::default());
+    }
+
+    #[test]
+    fn an_update_resets_a_silent_feed() {
+        let start = Instant::now();
+        let mut watchdog = watchdog(start);
+        watchdog.check_at(secs(start, 10));
+        watchdog.check_at(secs(start, 20));
+        watchdog.record_at(
+            &[
+                UpdateData::test("BTCUSD", 1, 0),
+                UpdateData::test("ETHUSD", 1, 0),
+            ],
+            secs(start, 21),
+        );
+
+        assert_eq!(watchdog.check_at(secs(start, 30)), StaleFeeds::default());
+        assert_eq!(
+            watchdog.check_at(secs(start, 31)),
+            stale(&["btc", "eth"], &[])
+        );
+    }
+
+    #[test]
+    fn ignores_updates_for_unwatched_symbols() {
+        let start = Instant::now();
+        let mut watchdog = watchdog(start);
+        watchdog.record_at(&[UpdateData::test("SOLUSD", 1, 0)], secs(start, 5));
+        assert_eq!(
+            watchdog.check_at(secs(start, 10)),
+            stale(&["btc", "eth"], &[])
+        );
+    }
+
+    #[test]
+    fn rewatching_keeps_timers_of_existing_feeds() {
+        let start = Instant::now();
+        let mut watchdog = watchdog(start);
+        watchdog.watch_at(
+            HashMap::from([
+                ("BTCUSD".to_string(), "btc".to_string()),
+                ("SOLUSD".to_string(), "sol".to_string()),
+            ]),
+            secs(start, 5),
+        );
+
+        assert_eq!(watchdog.check_at(secs(start, 10)), stale(&["btc"], &[]));
+        assert_eq!(watchdog.check_at(secs(start, 15)), stale(&["sol"], &[]));
+    }
+
+    #[test]
+    fn reconnects_only_when_opted_in_and_still_silent() {
+        assert!(!stale(&["btc"], &[]).reconnect(true));
+        assert!(!stale(&[], &["btc"]).reconnect(false));
+        assert!(stale(&[], &["btc"]).reconnect(true));
+    }
+}
@@ -0,0 +1,461 @@
+use bytes::{Bytes, BytesMut};
+use ratchet_rs::{
+    deflate::DeflateExtProvider, CloseCode, CloseReason, HeaderValue, Message, PayloadType,
+    TryIntoRequest, UpgradedClient, WebSocketClientBuilder, WebSocketStream,
+};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+use crate::args::DeliveryFormat;
+use crate::auth::Credentials;
+use crate::proxy::{self, ProxyConfig};
+use crate::reconnect::{EndpointHealth, ReconnectPolicy, STABLE_CONNECTION};
+use crate::subscriptions::{FeedChange, FeedSubscriptions};
+use crate::tls::TlsConfig;
+use crate::types::{ChainPusher, ProviderMessage, SubscriptionIds};
+use crate::watchdog::FeedWatchdog;
+
+/// Connection parameters shared by every WebSocket URL.
+#[derive(Clone)]
+pub struct ConnectionSettings {
+    pub credentials: Arc<Credentials>,
+    pub subscriptions: Arc<FeedSubscriptions>,
+    pub channel: String,
+    pub delivery_format: DeliveryFormat,
+    pub tls: TlsConfig,
+    pub proxy: Option<ProxyConfig>,
+    /// How long a feed may go without updates before the connection resubscribes it. `None`
+    /// disables the watchdog.
+    pub feed_stale_after: Option<Duration>,
+    /// Reconnect when feeds stay silent after being resubscribed, instead of only logging them.
+    pub feed_stale_reconnect: bool,
+    /// Set to `true` once the process should close its connections and stop.
+    pub shutdown: watch::Receiver<bool>,
+}
+
+type Frame = Result<(Message, Bytes), ratchet_rs::Error>;
+
+/// Time allowed to connect, including the proxy tunnel, TLS handshake and WebSocket upgrade.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Connects to one URL at a time, starting with the healthiest, and fails over to the next one
+/// when the connection drops. Returns once shutdown is requested.
+pub async fn run_failover_websocket_clients(
+    chain_pusher: Arc<dyn ChainPusher>,
+    ws_urls: Vec<String>,
+    settings: ConnectionSettings,
+    reconnect_policy: ReconnectPolicy,
+    endpoint_health: Arc<EndpointHealth>,
+) {
+    let mut shutdown = settings.shutdown.clone();
+    let mut attempt = 0;
+    loop {
+        let mut last_error = None;
+
+        for ws_url in endpoint_health.ranked(&ws_urls) {
+            match run_websocket_client(&chain_pusher, &ws_url, &settings, &endpoint_health).await {
+                Ok(_) => break,
+                Err(e) => {
+                    if endpoint_health.record_failure(&ws_url) >= STABLE_CONNECTION {
+                        attempt = 0;
+                    }
+                    error!(
+                        error = ?e,
+                        url = ws_url,
+                        score = endpoint_health.score(&ws_url),
+                        "WebSocket connection failed, trying next URL"
+                    );
+                    // kept as text, the boxed error isn't Send across the next attempt
+                    last_error = Some(format!("{:?}", e));
+                }
+            }
+        }
+
+        if *shutdown.borrow() {
+            return;
+        }
+
+        // if all URLs fail, back off before trying again
+        if let Some(e) = last_error {
+            let delay = reconnect_policy.delay(attempt);
+            attempt = attempt.saturating_add(1);
+            error!(error = %e, delay_ms = delay.as_millis() as u64, "All WebSocket URLs failed, backing off");
+            if sleep_or_shutdown(delay, &mut shutdown).await {
+                return;
+            }
+        }
+    }
+}
+
+/// Keeps one WebSocket connection per URL alive at the same time. Every connection feeds the same
+/// chain pusher, which drops the copies of an update that arrive after the first one.
+pub async fn run_redundant_websocket_clients(
+    chain_pusher: Arc<dyn ChainPusher>,
+    ws_urls: Vec<String>,
+    settings: ConnectionSettings,
+    reconnect_policy: ReconnectPolicy,
+    endpoint_health: Arc<EndpointHealth>,
+) {
+    info!(urls = ?ws_urls, "Connecting to all WebSocket URLs concurrently");
+
+    let mut clients = JoinSet::new();
+    for ws_url in ws_urls {
+        let chain_pusher = chain_pusher.clone();
+        let settings = settings.clone();
+        let reconnect_policy = reconnect_policy.clone();
+        let endpoint_health = endpoint_health.clone();
+        let mut shutdown = settings.shutdown.clone();
+
+        clients.spawn(async move {
+            let mut attempt = 0;
+            loop {
+                if let Err(e) =
+                    run_websocket_client(&chain_pusher, &ws_url, &settings, &endpoint_health).await
+                {
+                    error!(error = ?e, url = ws_url, "WebSocket connection failed");
+                }
+                if *shutdown.borrow() {
+                    return;
+                }
+                if endpoint_health.record_failure(&ws_url) >= STABLE_CONNECTION {
+                    attempt = 0;
+                }
+                let delay = reconnect_policy.delay(attempt);
+                attempt = attempt.saturating_add(1);
+                info!(
+                    url = ws_url,
+                    delay_ms = delay.as_millis() as u64,
+                    "Reconnecting after backoff"
+                );
+                if sleep_or_shutdown(delay, &mut shutdown).await {
+                    return;
+                }
+            }
+        });
+    }
+
+    while let Some(result) = clients.join_next().await {
+        if let Err(e) = result {
+            error!(error = ?e, "WebSocket client task stopped unexpectedly");
+        }
+    }
+}
+
+pub async fn run_websocket_client(
+    chain_pusher: &Arc<dyn ChainPusher>,
+    url: &str,
+    settings: &ConnectionSettings,
+    endpoint_health: &EndpointHealth,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut shutdown = settings.shutdown.clone();
+    if *shutdown.borrow() {
+        return Ok(());
+    }
+    info!(url = %url, "Establishing WebSocket connection");
+
+    let connect_started = Instant::now();
+    let endpoint = url;
+    let mut url = Url::parse(url)?;
+    let auth = settings
+        .credentials
+        .resolve(endpoint, chain_pusher.as_ref());
+    debug!(auth = ?auth, "Resolved provider credentials");
+    auth.apply_to_url(&mut url);
+    // Connecting, TLS, a proxy tunnel and the upgrade can each hang, so they share a timeout
+    // and give way to shutdown.
+    let connect = async {
+        let host = url.host_str().ok_or("Missing host in URL")?;
+        let stream =
+            proxy::connect(settings.proxy.as_ref(), host, url.port().unwrap_or(443)).await?;
+
+        let mut request = url.clone().try_into_request()?;
+        if let Some(authorization) = auth.authorization() {
+            request
+                .headers_mut()
+                .insert("AUTHORIZATION", HeaderValue::from_str(&authorization)?);
+        }
+
+        let stream: Box<dyn WebSocketStream> = if url.scheme() == "wss" {
+            settings.tls.connect(host, stream).await?
+        } else {
+            Box::new(stream)
+        };
+
+        let upgraded = WebSocketClientBuilder::default()
+            .extension(DeflateExtProvider::default())
+            .subscribe(stream, request)
+            .await?;
+        Ok::<_, Box<dyn std::error::Error>>(upgraded)
+    };
+    let upgraded = tokio::select! {
+        upgraded = time::timeout(CONNECT_TIMEOUT, connect) => {
+            upgraded.map_err(|_| "Timed out establishing WebSocket connection")??
+        }
+        _ = shutdown_requested(&mut shutdown) => {
+            info!("Shutdown requested while connecting");
+            return Ok(());
+        }
+    };
+
+    let UpgradedClient { websocket, .. } = upgraded;
+    endpoint_health.record_connected(endpoint, connect_started.elapsed());
+    info!("WebSocket connected.");
+
+    // Reads are not cancellation safe, so they run in their own task and hand complete frames
+    // over a channel that can be raced against feed subscription changes.
+    let (mut sender, mut receiver) = websocket.split()?;
+    let (frames_tx, mut frames) = mpsc::channel::<Frame>(1024);
+    let reader = tokio::spawn(async move {
+        let mut buf = BytesMut::new();
+        loop {
+            let frame = receiver
+                .read(&mut buf)
+                .await
+                .map(|message| (message, buf.split().freeze()));
+            let done = matches!(frame, Err(_) | Ok((Message::Close(_), _)));
+            if frames_tx.send(frame).await.is_err() || done {
+                break;
+            }
+        }
+    });
+
+    let mut changes = settings.subscriptions.changes();
+    let mut subscription_ids = SubscriptionIds::default();
+    let message_text = chain_pusher
+        .feeds_subscription_msg(
+            &settings.subscriptions.feeds(),
+            &settings.channel,
+            settings.delivery_format,
+            &mut subscription_ids,
+        )
+        .await?;
+
+    info!(message = %message_text, "Subscribing to price feeds");
+
+    sender
+        .write(message_text.as_bytes(), PayloadType::Text)
+        .await?;
+
+    info!("Subscribed to price feeds.");
+
+    let mut watchdog = settings.feed_stale_after.map(FeedWatchdog::new);
+    if let Some(watchdog) = &mut watchdog {
+        watchdog.watch(
+            chain_pusher
+                .feed_names(&settings.subscriptions.feeds())
+                .await?,
+        );
+    }
+    let mut watchdog_checks =
+        time::interval(settings.feed_stale_after.unwrap_or(Duration::from_secs(60)) / 2);
+
+    let result: Result<(), Box<dyn std::error::Error>> = async {
+        loop {
+            tokio::select! {
+                frame = time::timeout(Duration::from_secs(30), frames.recv()) => match frame {
+                    Ok(Some(Ok((message, payload)))) => match message {
+                        Message::Text | Message::Binary => {
+                            if let Some(message) = parse_frame(chain_pusher, &message, &payload) {
+                                if let (Some(watchdog), ProviderMessage::PriceUpdate(updates)) =
+                                    (&mut watchdog, &message)
+                                {
+                                    watchdog.record(updates);
+                                }
+                                handle_provider_message(
+                                    chain_pusher,
+                                    message,
+                                    settings,
+                                    &subscription_ids,
+                                )
+                                .await?;
+                            }
+                        }
+                        Message::Close(_) => return Err("WebSocket closed".into()),
+                        Message::Ping(payload) => {
+                            sender.write(&payload, PayloadType::Pong).await?;
+                        }
+                        Message::Pong(_) => {
+                            debug!("Received pong");
+                        }
+                    },
+                    Ok(Some(Err(e))) => return Err(e.into()),
+                    Ok(None) => return Err("WebSocket reader stopped".into()),
+                    Err(_) => {
+                        debug!("Sending ping");
+                        sender.write(&[], PayloadType::Ping).await?;
+                    }
+                },
+                change = changes.recv() => match change {
+                    Ok(change) => {
+                        let messages = chain_pusher
+                            .feeds_resubscription_msgs(
+                                &change,
+                                &settings.subscriptions.feeds(),
+                                &settings.channel,
+                                settings.delivery_format,
+                                &mut subscription_ids,
+                            )
+                            .await?;
+                        for message_text in messages {
+                            info!(message = %message_text, "Updating feed subscription");
+                            sender
+                                .write(message_text.as_bytes(), PayloadType::Text)
+                                .await?;
+                        }
+                        if let Some(watchdog) = &mut watchdog {
+                            watchdog.watch(
+                                chain_pusher
+                                    .feed_names(&settings.subscriptions.feeds())
+                                    .await?,
+                            );
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        return Err("Missed feed subscription changes, reconnecting".into())
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err("Feed subscriptions closed".into())
+                    }
+                },
+                _ = watchdog_checks.tick(), if watchdog.is_some() => {
+                    let Some(watchdog) = &mut watchdog else { continue };
+                    let stale = watchdog.check();
+                    if !stale.still_silent.is_empty() {
+                        if stale.reconnect(settings.feed_stale_reconnect) {
+                            warn!(
+                                feeds = ?stale.still_silent,
+                                "Feeds stayed silent after resubscribing, reconnecting"
+                            );
+                            return Err(
+                                format!("No updates for feeds {:?}", stale.still_silent).into()
+                            );
+                        }
+                        warn!(feeds = ?stale.still_silent, "Feeds stayed silent after resubscribing");
+                    }
+                    if !stale.resubscribe.is_empty() {
+                        warn!(
+                            feeds = ?stale.resubscribe,
+                            silent_secs = watchdog.stale_after().as_secs(),
+                            "No updates for feeds, resubscribing"
+                        );
+                        let messages = chain_pusher
+                            .feeds_resubscription_msgs(
+                                &FeedChange::Resubscribe(stale.resubscribe),
+                                &settings.subscriptions.feeds(),
+                                &settings.channel,
+                                settings.delivery_format,
+                                &mut subscription_ids,
+                            )
+                            .await?;
+                        for message_text in messages {
+                            sender
+                                .write(message_text.as_bytes(), PayloadType::Text)
+                                .await?;
+                        }
+                    }
+                }
+                _ = shutdown_requested(&mut shutdown) => {
+                    info!("Closing WebSocket connection");
+                    sender
+                        .close(CloseReason::new(
+                            CloseCode::GoingAway,
+                            Some("shutting down".to_string()),
+                        ))
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    .await;
+
+    reader.abort();
+    result
+}
+
+/// Sleeps for `delay` unless shutdown is requested first. Returns `true` on shutdown.
+async fn sleep_or_shutdown(delay: Duration, shutdown: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = time::sleep(delay) => false,
+        _ = shutdown_requested(shutdown) => true,
+    }
+}
+
+/// Resolves once shutdown is requested.
+async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stopping| *stopping).await;
+}
+
+fn parse_frame(
+    chain_pusher: &Arc<dyn ChainPusher>,
+    kind: &Message,
+    payload: &[u8],
+) -> Option<ProviderMessage> {
+    if let Message::Binary = kind {
+        chain_pusher
+            .parse_binary_message(payload)
+            .inspect_err(
+                |e| warn!(error = ?e, len = payload.len(), "Failed to parse binary price update"),
+            )
+            .ok()
+    } else {
+        let message = String::from_utf8_lossy(payload);
+        chain_pusher
+            .parse_message(&message)
+            .inspect_err(|e| warn!(error = ?e, message = %message, "Failed to parse price update"))
+            .ok()
+    }
+}
+
+/// Pushes price updates on-chain and reports everything else the provider sends. A rejected
+/// subscription ends the connection so the misconfiguration doesn't go unnoticed.
+async fn handle_provider_message(
+    chain_pusher: &Arc<dyn ChainPusher>,
+    message: ProviderMessage,
+    settings: &ConnectionSettings,
+    subscription_ids: &SubscriptionIds,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match message {
+        ProviderMessage::PriceUpdate(updates) => {
+            if let Err(e) = chain_pusher.send_price_updates(updates).await {
+                warn!(error = ?e, "Failed to send price updates")
+            } else {
+                debug!("Processed price updates");
+            }
+        }
+        ProviderMessage::SubscriptionAck { ignored_feed_ids } => {
+            if ignored_feed_ids.is_empty() {
+                info!("Subscription acknowledged");
+            } else {
+                warn!(feed_ids = ?ignored_feed_ids, "Subscription acknowledged, invalid feeds were ignored");
+            }
+        }
+        ProviderMessage::SubscriptionError {
+            error,
+            mut feed_ids,
+            subscription_id,
+        } => {
+            if let Some(feeds) = subscription_id.and_then(|id| subscription_ids.feeds(id)) {
+                if feed_ids.is_empty() {
+                    feed_ids = feeds.to_vec();
+                }
+            }
+            error!(
+                subscription_id = ?subscription_id,
+                error = %error,
+                feed_ids = ?feed_ids,
+                subscribed_feeds = ?settings.subscriptions.feeds(),
+                "Subscription rejected by provider"
+            );
+            return Err(format!("Subscription error: {}", error).into());
+        }
+        ProviderMessage::Other(kind) => {
+            debug!(kind = %kind, "Ignoring provider message");
+        }
+    }
+    Ok(())
+}